@@ -1,159 +1,336 @@
-use gfx;
-use gfx::Bundle;
-use gfx_app;
-use gfx_app::ColorFormat;
-use winit::{Event, ElementState, VirtualKeyCode};
-
-use state::{State, Visible, PREVIEW_WIDTH};
-use state::template::DeltaPos;
-use state::color::Color;
-
-gfx_defines!{
-    vertex Vertex {
-        pos: [f32; 2] = "pos",
-    }
+use std::cmp;
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
 
-    pipeline pipe {
-        color: gfx::Global<[f32; 3]> = "u_color",
-        center: gfx::Global<[f32; 2]> = "u_center",
-        vbuf: gfx::VertexBuffer<Vertex> = (),
-        out_color: gfx::RenderTarget<ColorFormat> = "target",
-        clear_color: gfx::Global<[f32; 4]> = "color",
-    }
-}
+use gfx::{self, Device};
+use gfx_app::{self, Application, WindowTargets};
+use gfx_app::shade::Backend;
+use gfx_device_gl;
+use gfx_window_glutin;
+use glutin;
+use winit::{Event, ElementState, VirtualKeyCode, WindowBuilder};
+
+use state::mode::MODES;
+use state::timer::tick;
+use game::Game;
+use menu::{self, Screen, Item, ITEMS, SETTINGS};
+use keys::{self, ACTIONS, Bindings};
+use replay::Viewer;
+use scene::Scene;
+use skin::Skin;
+use raster;
+use renderer::Renderer;
+use config::{Config, CONFIG_PATH};
+use BOX_SIZE;
+
+/// Longest stretch of time caught up on in one frame, so a stall doesn't fast-forward the game.
+const MAX_LAG_MILLIS: u64 = 250;
 
 pub struct App<R: gfx::Resources>{
-    bundle: Bundle<R, pipe::Data<R>>,
-    state: State,
+    renderer: Renderer<R>,
+    /// Settings for the next game, as the settings and key screens leave them.
+    config: Config,
+    skin: Skin,
+    screen: Screen,
+    last_frame: Instant,
+    lag: Duration,
 }
 
 impl<R: gfx::Resources> gfx_app::Application<R> for App<R> {
     fn new<F: gfx::Factory<R>>(factory: &mut F,
                                backend: gfx_app::shade::Backend,
                                window_targets: gfx_app::WindowTargets<R>) -> Self {
-        use gfx::traits::FactoryExt;
-
-        let vs = gfx_app::shade::Source {
-            glsl_150: include_bytes!("shader/tetris_150.glslv"),
-            .. gfx_app::shade::Source::empty()
-        };
-        let ps = gfx_app::shade::Source {
-            glsl_150: include_bytes!("shader/tetris_150.glslf"),
-            .. gfx_app::shade::Source::empty()
-        };
-
-        let state = State::new();
-        let width = state.box_width();
-        let height = state.box_height();
-
-        let vertices = [
-            Vertex { pos: [-width, -height] },
-            Vertex { pos: [-width,  height] },
-            Vertex { pos: [ width, -height] },
-            Vertex { pos: [ width,  height] },
-        ];
-        let indices = [0u16, 1, 2, 1, 2, 3];
-        let (vertex_buffer, slice) = factory.create_vertex_buffer_with_slice(&vertices, &indices as &[u16]);
-
-        let data = pipe::Data {
-            color: Color::default().into(),
-            center: [-2.0, -2.0],
-            vbuf: vertex_buffer,
-            out_color: window_targets.color,
-            clear_color: [0.1, 0.1, 0.1, 1.0],
-        };
-
-        let pso = factory.create_pipeline_simple(
-            vs.select(backend).unwrap(),
-            ps.select(backend).unwrap(),
-            pipe::new()
-        ).unwrap();
+        // main already reported what's wrong with it
+        let config = Config::load_quietly(CONFIG_PATH);
+        let skin = Skin::new(&config);
+        let scene = Scene::menu("", &[], None);
+        let renderer = Renderer::new(factory, backend, window_targets, scene.width, scene.height,
+                                     config.integer_scale, &skin);
 
         App {
-            bundle: Bundle::new(slice, pso, data),
-            state: state,
+            renderer: renderer,
+            config: config,
+            skin: skin,
+            screen: Screen::Main(0),
+            last_frame: Instant::now(),
+            lag: Duration::from_secs(0),
         }
     }
 
     fn render<C: gfx::CommandBuffer<R>>(&mut self, encoder: &mut gfx::Encoder<R, C>) {
-        if !self.state.is_gameover && self.state.timer.is_up() {
-            self.state.draw_piece(Visible::No);
-            if self.state.move_piece(DeltaPos { dx: 0, dy: 1 }).is_err() {
-                self.state.draw_piece(Visible::Yes);
-                self.state.collapse_rows();
-                if self.state.spawn_piece().is_err() {
-                    println!("Game over\nYour score: {}", self.state.score);
-                }
-                else {
-                    self.state.draw_piece(Visible::Yes);
-                }
+        let now = Instant::now();
+        self.lag = cmp::min(self.lag + (now - self.last_frame), Duration::from_millis(MAX_LAG_MILLIS));
+        self.last_frame = now;
+        while self.lag >= tick() {
+            self.lag -= tick();
+            match self.screen {
+                Screen::Playing(ref mut game) => game.tick(),
+                Screen::Watching(ref mut viewer) => viewer.tick(),
+                _ => (),
             }
-            else {
-                self.state.draw_piece(Visible::Yes);
-            }
-        }
-        let mut data = self.bundle.data.clone();
-        let box_width = self.state.box_width();
-        let box_height = self.state.box_height();
-        let middle_y = self.state.dim().h as f32 / 2.0 - 0.5;
-        let middle_x = self.state.dim().w as f32 / 2.0 - 0.5;
-        encoder.clear(&data.out_color, data.clear_color);
-        for pos in self.state.main.get_iter() {
-            let x =   (pos.x as f32 / middle_x - 1.0) * (1.0 - box_width);
-            let y = - (pos.y as f32 / middle_y - 1.0) * (1.0 - box_height);
-            data.center = [x, y];
-            data.color = self.state.main.tile(pos).into();
-            encoder.draw(&self.bundle.slice, &self.bundle.pso, &data);
         }
-        let offset = self.state.dim().w - PREVIEW_WIDTH;
-        for pos in self.state.preview.get_iter() {
-            let x = ((pos.x + offset) as f32 / middle_x - 1.0) * (1.0 - box_width);
-            let y = - (pos.y as f32 / middle_y - 1.0) * (1.0 - box_height);
-            data.center = [x, y];
-            data.color = self.state.preview.tile(pos).into();
-            encoder.draw(&self.bundle.slice, &self.bundle.pso, &data);
+        if let Screen::Playing(ref mut game) = self.screen {
+            game.handle_events();
         }
-        self.bundle.encode(encoder);
+        let scene = menu::scene(&self.screen, &self.config);
+        self.renderer.draw(encoder, &scene, &self.skin);
+    }
+
+    fn on_resize(&mut self, window_targets: gfx_app::WindowTargets<R>) {
+        self.renderer.resize(window_targets);
     }
 
     fn on(&mut self, event: Event) {
-        if self.state.is_gameover {
-            return;
-        }
         match event {
-            Event::KeyboardInput(ElementState::Pressed, _, Some(VirtualKeyCode::Left)) => {
-                self.state.draw_piece(Visible::No);
-                let _ = self.state.move_piece(DeltaPos { dx: -1, dy: 0 });
-                self.state.draw_piece(Visible::Yes);
+            Event::KeyboardInput(ElementState::Pressed, _, Some(key)) => self.on_press(key),
+            Event::KeyboardInput(ElementState::Released, _, Some(key)) => {
+                if let Screen::Playing(ref mut game) = self.screen {
+                    game.on_release(key);
+                }
             },
-            Event::KeyboardInput(ElementState::Pressed, _, Some(VirtualKeyCode::Right)) => {
-                self.state.draw_piece(Visible::No);
-                let _ = self.state.move_piece(DeltaPos { dx: 1, dy: 0 });
-                self.state.draw_piece(Visible::Yes);
+            _ => (),
+        }
+    }
+}
+
+impl<R: gfx::Resources> App<R> {
+    /// Moves between screens: arrows pick, return goes in, escape comes back out.
+    fn on_press(&mut self, key: VirtualKeyCode) {
+        let next = match self.screen {
+            Screen::Playing(ref mut game) => {
+                if key == VirtualKeyCode::Escape || (key == VirtualKeyCode::Return && game.is_over()) {
+                    Some(Screen::Main(0))
+                }
+                else {
+                    if !game.on_press(key) {
+                        on_view_key(&mut self.config, &mut self.skin, key, &game.scene());
+                    }
+                    None
+                }
             },
-            Event::KeyboardInput(ElementState::Pressed, _, Some(VirtualKeyCode::Down)) => {
-                self.state.draw_piece(Visible::No);
-                let _ = self.state.move_piece(DeltaPos { dx: 0, dy: 1 });
-                self.state.draw_piece(Visible::Yes);
+            Screen::Watching(ref mut viewer) => match key {
+                VirtualKeyCode::Escape => Some(Screen::Replays { selected: 0, files: menu::replay_files() }),
+                VirtualKeyCode::Left => { viewer.step(-1); None },
+                VirtualKeyCode::Right => { viewer.step(1); None },
+                VirtualKeyCode::Space => { viewer.is_paused = !viewer.is_paused; None },
+                _ => {
+                    on_view_key(&mut self.config, &mut self.skin, key, &viewer.scene());
+                    None
+                },
             },
-            Event::KeyboardInput(ElementState::Pressed, _, Some(VirtualKeyCode::Up)) => {
-                self.state.draw_piece(Visible::No);
-                self.state.rotate_piece();
-                self.state.draw_piece(Visible::Yes);
-            }
-            Event::KeyboardInput(ElementState::Pressed, _, Some(VirtualKeyCode::Space)) => {
-                self.state.draw_piece(Visible::No);
-                self.state.hard_drop();
-                self.state.draw_piece(Visible::Yes);
-                self.state.collapse_rows();
-                if self.state.spawn_piece().is_err() {
-                    println!("Game over\nYour score: {}", self.state.score);
+            Screen::Main(ref mut selected) => match key {
+                VirtualKeyCode::Escape => Some(Screen::Quit),
+                VirtualKeyCode::Return => match ITEMS[*selected] {
+                    Item::Play => self.start_game(),
+                    Item::Modes => Some(Screen::Modes(MODES.iter().position(|&mode| mode == self.config.mode).unwrap_or(0))),
+                    Item::Settings => Some(Screen::Settings(0)),
+                    Item::Keys => Some(Screen::Keys { selected: 0, is_binding: false }),
+                    Item::Scores => Some(Screen::Scores(0)),
+                    Item::Replays => Some(Screen::Replays { selected: 0, files: menu::replay_files() }),
+                    Item::Quit => Some(Screen::Quit),
+                },
+                _ => {
+                    *selected = menu::select(*selected, ITEMS.len(), key);
+                    None
+                },
+            },
+            Screen::Modes(ref mut selected) => match key {
+                VirtualKeyCode::Escape => Some(Screen::Main(1)),
+                VirtualKeyCode::Return => {
+                    self.config.mode = MODES[*selected];
+                    store("mode", MODES[*selected].name());
+                    self.start_game()
+                },
+                _ => {
+                    *selected = menu::select(*selected, MODES.len(), key);
+                    None
+                },
+            },
+            Screen::Settings(ref mut selected) => match key {
+                VirtualKeyCode::Escape => Some(Screen::Main(2)),
+                VirtualKeyCode::Left | VirtualKeyCode::Right | VirtualKeyCode::Return => {
+                    let setting = SETTINGS[*selected];
+                    setting.change(&mut self.config, &mut self.skin, if key == VirtualKeyCode::Left { -1 } else { 1 });
+                    let (name, value) = setting.value(&self.config);
+                    store(name, &value);
+                    None
+                },
+                _ => {
+                    *selected = menu::select(*selected, SETTINGS.len(), key);
+                    None
+                },
+            },
+            Screen::Keys { ref mut selected, ref mut is_binding } => {
+                if *is_binding {
+                    // escape leaves the key as it was
+                    if keys::is_bindable(key) {
+                        self.config.keys.set(ACTIONS[*selected], key);
+                        store_keys(&self.config.keys);
+                    }
+                    *is_binding = false;
+                    None
                 }
                 else {
-                    self.state.draw_piece(Visible::Yes);
+                    match key {
+                        VirtualKeyCode::Escape => Some(Screen::Main(3)),
+                        VirtualKeyCode::Return if *selected == ACTIONS.len() => {
+                            self.config.keys = Bindings::default();
+                            store_keys(&self.config.keys);
+                            None
+                        },
+                        VirtualKeyCode::Return => {
+                            *is_binding = true;
+                            None
+                        },
+                        _ => {
+                            // the actions and a last line putting them all back
+                            *selected = menu::select(*selected, ACTIONS.len() + 1, key);
+                            None
+                        },
+                    }
                 }
+            },
+            Screen::Scores(ref mut first) => match key {
+                VirtualKeyCode::Escape => Some(Screen::Main(4)),
+                VirtualKeyCode::Up => {
+                    *first = first.saturating_sub(1);
+                    None
+                },
+                VirtualKeyCode::Down => {
+                    *first = cmp::min(*first + 1, menu::score_lines().len().saturating_sub(1));
+                    None
+                },
+                _ => None,
+            },
+            Screen::Replays { ref mut selected, ref files } => match key {
+                VirtualKeyCode::Escape => Some(Screen::Main(5)),
+                VirtualKeyCode::Return if !files.is_empty() => {
+                    match Viewer::new(&self.config, &files[*selected].to_string_lossy()) {
+                        Ok(viewer) => Some(Screen::Watching(viewer)),
+                        Err(err) => {
+                            eprintln!("Can't load replay {}: {}", files[*selected].display(), err);
+                            None
+                        },
+                    }
+                },
+                _ if !files.is_empty() => {
+                    *selected = menu::select(*selected, files.len(), key);
+                    None
+                },
+                _ => None,
+            },
+            Screen::Quit => None,
+        };
+        if let Some(screen) = next {
+            self.screen = screen;
+        }
+    }
+
+    /// Whether the menu was quit, for `launch` to close the window.
+    fn is_quitting(&self) -> bool {
+        match self.screen {
+            Screen::Quit => true,
+            _ => false,
+        }
+    }
+
+    /// A new game as the config has it, staying on the menu if it can't be set up.
+    fn start_game(&self) -> Option<Screen> {
+        match Game::new(&self.config) {
+            Ok(game) => Some(Screen::Playing(game)),
+            Err(err) => {
+                eprintln!("{}", err);
+                None
+            },
+        }
+    }
+}
+
+/// Opens the window and runs the app in it, as gfx_app's own GL loop does, but stopping when
+/// the menu is quit rather than on a fixed exit key.
+pub fn launch(wb: WindowBuilder) {
+    let gl_version = glutin::GlRequest::GlThenGles {
+        opengl_version: (3, 2),
+        opengles_version: (2, 0),
+    };
+    let builder = glutin::WindowBuilder::from_winit_builder(wb)
+        .with_gl(gl_version)
+        .with_vsync();
+    let (window, mut device, mut factory, color, depth) =
+        gfx_window_glutin::init::<gfx_app::ColorFormat, gfx_app::DepthFormat>(builder);
+    let (mut width, mut height) = window.get_inner_size_points().unwrap();
+    let shade_lang = device.get_info().shading_language;
+    let backend = if shade_lang.is_embedded { Backend::GlslEs(shade_lang) } else { Backend::Glsl(shade_lang) };
+    let mut app: App<gfx_device_gl::Resources> = App::new(&mut factory, backend, WindowTargets {
+        color: color,
+        depth: depth,
+        aspect_ratio: width as f32 / height as f32,
+    });
+    let mut encoder: gfx::Encoder<_, _> = factory.create_command_buffer().into();
+
+    loop {
+        for event in window.poll_events() {
+            match event {
+                Event::Closed => return,
+                Event::Resized(new_width, new_height) => if new_width != width || new_height != height {
+                    width = new_width;
+                    height = new_height;
+                    let (color, depth) = gfx_window_glutin::new_views(&window);
+                    app.on_resize(WindowTargets {
+                        color: color,
+                        depth: depth,
+                        aspect_ratio: width as f32 / height as f32,
+                    });
+                },
+                _ => app.on(event),
+            }
+            if app.is_quitting() {
+                return;
             }
-            _ => (),
         }
+        app.render(&mut encoder);
+        encoder.flush(&mut device);
+        window.swap_buffers().unwrap();
+        device.cleanup();
+    }
+}
+
+/// Keys for whatever is on the board: switching skins, the grid and screenshots. The skin
+/// is kept in `config` for the settings screen, but only saved from there.
+fn on_view_key(config: &mut Config, skin: &mut Skin, key: VirtualKeyCode, scene: &Scene) {
+    match key {
+        VirtualKeyCode::K => {
+            skin.next_style();
+            config.skin = skin.style;
+            println!("Skin: {:?}", skin.style);
+        },
+        VirtualKeyCode::G => {
+            skin.toggle_grid();
+            config.grid = skin.has_grid;
+        },
+        VirtualKeyCode::P => screenshot(scene, skin),
+        _ => (),
+    }
+}
+
+/// Saves the window as it is, in boxes of their usual size, whatever its size on screen.
+fn screenshot(scene: &Scene, skin: &Skin) {
+    let secs = SystemTime::now().duration_since(UNIX_EPOCH).map(|time| time.as_secs()).unwrap_or(0);
+    let path = format!("screenshot-{}.png", secs);
+    match raster::render(scene, skin, BOX_SIZE).save_png(&path) {
+        Ok(()) => println!("Saved {}", path),
+        Err(err) => eprintln!("Can't save {}: {}", path, err),
+    }
+}
+
+/// Keeps a setting changed on the menus for next time.
+fn store(key: &str, value: &str) {
+    if let Err(err) = Config::store(CONFIG_PATH, key, value) {
+        eprintln!("config: can't save {}: {}", CONFIG_PATH, err);
+    }
+}
+
+fn store_keys(bindings: &Bindings) {
+    for &action in ACTIONS.iter() {
+        store(&format!("key_{}", action.name()), keys::name(bindings.key(action)));
     }
 }
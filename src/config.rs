@@ -0,0 +1,233 @@
+use std::fs::{self, File};
+use std::io::{self, BufRead, BufReader};
+use std::path::Path;
+
+use state::mode::Mode;
+use state::kick::HalfTurnKicks;
+use state::ruleset::Rules;
+use skin::Style;
+use keys::{self, Bindings, ACTIONS};
+
+pub const CONFIG_PATH: &'static str = "tetris.cfg";
+pub const MAX_PREVIEW: usize = 7;
+/// Longest entry or line clear delay, in ticks.
+pub const MAX_DELAY: usize = 120;
+pub const SPRINT_LINES: [usize; 3] = [20, 40, 100];
+
+#[derive(Clone, Debug)]
+pub struct Config {
+    pub preview: usize,
+    pub mode: Mode,
+    pub sprint_lines: usize,
+    pub ultra_seconds: u64,
+    /// Levels to clear in marathon, ten lines each; zero plays on without end.
+    pub marathon_levels: u32,
+    pub dig_lines: usize,
+    /// Chance from 0 to 1 that a garbage hole moves away from the column of the row below,
+    /// shared by dig and survival.
+    pub garbage_messiness: f64,
+    pub puzzle_pack: String,
+    pub rules: Rules,
+    /// Level the NES ruleset starts on, 0 to 29.
+    pub start_level: u32,
+    /// Ticks before each piece comes in, instead of the ruleset's.
+    pub are: Option<u32>,
+    /// Ticks full rows stay before they collapse, instead of the ruleset's.
+    pub line_clear_delay: Option<u32>,
+    pub half_turn_kicks: HalfTurnKicks,
+    /// Whether garbage pushing the stack through the ceiling ends the game.
+    pub garbage_top_out: bool,
+    /// Whether a piece locking even partly above the skyline ends the game,
+    /// not only one locking entirely above it.
+    pub partial_lock_out: bool,
+    /// Points added for a lock that empties the board.
+    pub perfect_clear_bonus: u64,
+    /// A v115 fumen string to start from instead of an empty board.
+    pub fumen: Option<String>,
+    /// Prints every game event as it happens.
+    pub log_events: bool,
+    /// Keeps every finished game as a fumen in `replays`, to watch from the menu.
+    pub save_replays: bool,
+    pub keys: Bindings,
+    /// Fading clears, the stack settling and the board dipping on hard drops.
+    pub animations: bool,
+    /// Text naming tetrises, spins, back-to-backs and combos as they're cleared.
+    pub callouts: bool,
+    /// Sparks from line clears and trails behind hard drops.
+    pub particles: bool,
+    /// How cells are drawn at the start; K switches between styles while playing.
+    pub skin: Style,
+    /// PNG of sprites for the atlas style.
+    pub skin_atlas: String,
+    /// Lines between the empty cells of the board.
+    pub grid: bool,
+    /// Starts on the whole of the main monitor rather than in a window.
+    pub fullscreen: bool,
+    /// Draws boxes a whole number of pixels across, leaving a wider border.
+    pub integer_scale: bool,
+    /// Size of the window at the start, times the 20 pixel box; by default it goes by the screen.
+    pub window_scale: Option<f64>,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Config {
+            preview: 3,
+            mode: Mode::Endless,
+            sprint_lines: 40,
+            ultra_seconds: 120,
+            marathon_levels: 15,
+            dig_lines: 18,
+            garbage_messiness: 0.5,
+            puzzle_pack: "puzzles.txt".to_string(),
+            rules: Rules::Srs,
+            start_level: 0,
+            are: None,
+            line_clear_delay: None,
+            half_turn_kicks: HalfTurnKicks::SrsPlus,
+            garbage_top_out: true,
+            partial_lock_out: false,
+            perfect_clear_bonus: 2000,
+            fumen: None,
+            log_events: false,
+            save_replays: true,
+            keys: Bindings::default(),
+            animations: true,
+            callouts: true,
+            particles: true,
+            skin: Style::Flat,
+            skin_atlas: "skin.png".to_string(),
+            grid: false,
+            fullscreen: false,
+            integer_scale: false,
+            window_scale: None,
+        }
+    }
+}
+
+impl Config {
+    /// Reads `key = value` lines from `path` on top of the defaults.
+    /// A missing file is not an error, unknown keys and bad values are reported and skipped.
+    pub fn load<P: AsRef<Path>>(path: P) -> Self {
+        Config::read(path, true)
+    }
+
+    /// Like `load` but skips bad lines without a word, for a second read of the same file.
+    pub fn load_quietly<P: AsRef<Path>>(path: P) -> Self {
+        Config::read(path, false)
+    }
+
+    fn read<P: AsRef<Path>>(path: P, is_reported: bool) -> Self {
+        let mut config = Config::default();
+        let file = match File::open(path) {
+            Ok(file) => file,
+            Err(_) => return config,
+        };
+        for line in BufReader::new(file).lines().filter_map(|line| line.ok()) {
+            let line = line.split('#').next().unwrap().trim();
+            if line.is_empty() {
+                continue;
+            }
+            let mut parts = line.splitn(2, '=').map(|part| part.trim());
+            let message = match (parts.next(), parts.next()) {
+                (Some(key), Some(value)) => match config.set(key, value) {
+                    Ok(true) => continue,
+                    Ok(false) => format!("config: unknown key `{}`", key),
+                    Err(()) => format!("config: bad value for `{}`: {}", key, value),
+                },
+                _ => format!("config: can't parse `{}`", line),
+            };
+            if is_reported {
+                eprintln!("{}", message);
+            }
+        }
+        config
+    }
+
+    /// Sets `key` from `value`, returning whether the key is known.
+    fn set(&mut self, key: &str, value: &str) -> Result<bool, ()> {
+        match key {
+            "preview" => self.preview = parse_in(value, 0, MAX_PREVIEW)?,
+            "mode" => self.mode = value.parse()?,
+            "sprint_lines" => {
+                let lines = value.parse().map_err(|_| ())?;
+                if !SPRINT_LINES.contains(&lines) {
+                    return Err(());
+                }
+                self.sprint_lines = lines;
+            },
+            "ultra_seconds" => self.ultra_seconds = parse_in(value, 1, 3600)? as u64,
+            "marathon_levels" => self.marathon_levels = parse_in(value, 0, 100)? as u32,
+            "dig_lines" => self.dig_lines = parse_in(value, 1, 1000)?,
+            // `dig_messiness` is its name from before survival had garbage too
+            "garbage_messiness" | "dig_messiness" => match value.parse::<f64>() {
+                Ok(p) if 0.0 <= p && p <= 1.0 => self.garbage_messiness = p,
+                _ => return Err(()),
+            },
+            "puzzle_pack" => self.puzzle_pack = value.to_string(),
+            "ruleset" => self.rules = value.parse()?,
+            "start_level" => self.start_level = parse_in(value, 0, 29)? as u32,
+            "are" => self.are = Some(parse_in(value, 0, MAX_DELAY)? as u32),
+            "line_clear_delay" => self.line_clear_delay = Some(parse_in(value, 0, MAX_DELAY)? as u32),
+            "half_turn_kicks" => self.half_turn_kicks = value.parse()?,
+            "garbage_top_out" => self.garbage_top_out = value.parse().map_err(|_| ())?,
+            "partial_lock_out" => self.partial_lock_out = value.parse().map_err(|_| ())?,
+            "perfect_clear_bonus" => self.perfect_clear_bonus = parse_in(value, 0, 1000000)? as u64,
+            "fumen" => self.fumen = Some(value.to_string()),
+            "log_events" => self.log_events = value.parse().map_err(|_| ())?,
+            "save_replays" => self.save_replays = value.parse().map_err(|_| ())?,
+            "animations" => self.animations = value.parse().map_err(|_| ())?,
+            "callouts" => self.callouts = value.parse().map_err(|_| ())?,
+            "particles" => self.particles = value.parse().map_err(|_| ())?,
+            "skin" => self.skin = value.parse()?,
+            "skin_atlas" => self.skin_atlas = value.to_string(),
+            "grid" => self.grid = value.parse().map_err(|_| ())?,
+            "fullscreen" => self.fullscreen = value.parse().map_err(|_| ())?,
+            "integer_scale" => self.integer_scale = value.parse().map_err(|_| ())?,
+            "window_scale" => match value.parse::<f64>() {
+                Ok(scale) if 0.5 <= scale && scale <= 4.0 => self.window_scale = Some(scale),
+                _ => return Err(()),
+            },
+            _ if key.starts_with("key_") => {
+                let action = match ACTIONS.iter().find(|action| action.name() == &key[4..]) {
+                    Some(&action) => action,
+                    None => return Ok(false),
+                };
+                self.keys.set(action, keys::parse(value).ok_or(())?);
+            },
+            _ => return Ok(false),
+        }
+        Ok(true)
+    }
+
+    /// Writes `key = value` to the file at `path` in place of the line setting `key`, or at
+    /// the end if there's none, leaving the rest of the file and its comments alone.
+    pub fn store<P: AsRef<Path>>(path: P, key: &str, value: &str) -> io::Result<()> {
+        let text = fs::read_to_string(path.as_ref()).unwrap_or_default();
+        let setting = format!("{} = {}", key, value);
+        let mut is_stored = false;
+        let mut lines: Vec<String> = text.lines()
+            .map(|line| {
+                let is_key = line.split('#').next().unwrap().splitn(2, '=').next().unwrap().trim() == key;
+                if is_key && line.contains('=') && !is_stored {
+                    is_stored = true;
+                    setting.clone()
+                }
+                else {
+                    line.to_string()
+                }
+            })
+            .collect();
+        if !is_stored {
+            lines.push(setting);
+        }
+        fs::write(path, lines.join("\n") + "\n")
+    }
+}
+
+fn parse_in(value: &str, min: usize, max: usize) -> Result<usize, ()> {
+    match value.parse::<usize>() {
+        Ok(n) if min <= n && n <= max => Ok(n),
+        _ => Err(()),
+    }
+}
@@ -0,0 +1,152 @@
+//! Animations the renderer plays on top of the game, started by its events: cleared rows
+//! fading out as the stack settles into their place, a dip of the board on hard drops
+//! and text callouts for the clears worth a mention.
+
+use state::event::Event;
+use state::HEIGHT;
+
+const CLEAR_TICKS: u32 = 12;
+const SHAKE_TICKS: u32 = 8;
+/// How far the board dips on a hard drop, in boxes.
+const SHAKE_DEPTH: f32 = 0.25;
+const CALLOUT_TICKS: u32 = 90;
+/// Closing ticks of a callout spent fading out.
+const CALLOUT_FADE_TICKS: u32 = 30;
+
+pub struct Effects {
+    is_animated: bool,
+    has_callouts: bool,
+    /// Rows of the last clear, where they were before the stack collapsed.
+    cleared: Vec<usize>,
+    /// Rows each row of the board fell in the last collapse.
+    fallen: Vec<usize>,
+    clear_ticks: u32,
+    shake_ticks: u32,
+    callouts: Vec<String>,
+    callout_ticks: u32,
+}
+
+impl Effects {
+    pub fn new(is_animated: bool, has_callouts: bool) -> Self {
+        Effects {
+            is_animated: is_animated,
+            has_callouts: has_callouts,
+            cleared: Vec::new(),
+            fallen: vec![0; HEIGHT],
+            clear_ticks: 0,
+            shake_ticks: 0,
+            callouts: Vec::new(),
+            callout_ticks: 0,
+        }
+    }
+
+    pub fn is_animated(&self) -> bool {
+        self.is_animated
+    }
+
+    pub fn tick(&mut self) {
+        self.clear_ticks = self.clear_ticks.saturating_sub(1);
+        self.shake_ticks = self.shake_ticks.saturating_sub(1);
+        self.callout_ticks = self.callout_ticks.saturating_sub(1);
+    }
+
+    pub fn on(&mut self, event: &Event) {
+        match *event {
+            Event::LinesCleared { ref rows, spin, combo, b2b, .. } => {
+                if self.is_animated {
+                    self.start_clear(rows);
+                }
+                if self.has_callouts {
+                    self.callouts = callouts(rows.len(), spin, combo, b2b);
+                    self.callout_ticks = if self.callouts.is_empty() { 0 } else { CALLOUT_TICKS };
+                }
+            },
+            Event::PerfectClear if self.has_callouts => {
+                self.callouts.push("PERFECT CLEAR".to_string());
+                self.callout_ticks = CALLOUT_TICKS;
+            },
+            Event::Dropped(rows) if self.is_animated && rows > 0 => self.shake_ticks = SHAKE_TICKS,
+            _ => (),
+        }
+    }
+
+    fn start_clear(&mut self, rows: &[usize]) {
+        self.cleared = rows.to_vec();
+        // rows come down once for every cleared row below them, the empty ones
+        // coming in at the top as far as the whole clear
+        self.fallen = vec![rows.len(); HEIGHT];
+        for y in (0..HEIGHT).filter(|y| !rows.contains(y)) {
+            let fall = rows.iter().filter(|&&row| row > y).count();
+            self.fallen[y + fall] = fall;
+        }
+        self.clear_ticks = CLEAR_TICKS;
+    }
+
+    /// How much of the clear animation is left, from 1 down to 0.
+    fn clear_left(&self) -> f32 {
+        self.clear_ticks as f32 / CLEAR_TICKS as f32
+    }
+
+    /// Rows being cleared, where they were, and how far they've faded from 0 to 1.
+    pub fn fading_rows(&self) -> Option<(&[usize], f32)> {
+        match self.clear_ticks {
+            0 => None,
+            _ => Some((&self.cleared, 1.0 - self.clear_left())),
+        }
+    }
+
+    /// Rows above its place that row `y` of the board is drawn while the stack settles.
+    pub fn lift(&self, y: usize) -> f32 {
+        match self.clear_ticks {
+            0 => 0.0,
+            _ => self.fallen[y] as f32 * self.clear_left(),
+        }
+    }
+
+    /// Rows the board is drawn below its place.
+    pub fn dip(&self) -> f32 {
+        SHAKE_DEPTH * self.shake_ticks as f32 / SHAKE_TICKS as f32
+    }
+
+    /// Lines of the callout on show and its brightness from 0 to 1.
+    pub fn callouts(&self) -> Option<(&[String], f32)> {
+        match self.callout_ticks {
+            0 => None,
+            ticks => Some((&self.callouts, (ticks as f32 / CALLOUT_FADE_TICKS as f32).min(1.0))),
+        }
+    }
+}
+
+/// Text for a clear of `rows`: tetrises and spins by name, back-to-back and combo on lines
+/// of their own.
+fn callouts(rows: usize, spin: bool, combo: usize, b2b: bool) -> Vec<String> {
+    let name = match rows {
+        1 => "SINGLE",
+        2 => "DOUBLE",
+        3 => "TRIPLE",
+        _ => "TETRIS",
+    };
+    let mut lines = Vec::new();
+    if b2b {
+        lines.push("B2B".to_string());
+    }
+    if spin {
+        lines.push(format!("T-SPIN {}", name));
+    }
+    else if rows == 4 {
+        lines.push(name.to_string());
+    }
+    if combo > 0 {
+        lines.push(format!("{} COMBO", combo));
+    }
+    lines
+}
+
+/// `from` blended towards `to` by `t`, from 0 to 1.
+pub fn mix(from: [f32; 3], to: [f32; 3], t: f32) -> [f32; 3] {
+    let mut result = from;
+    for (channel, &target) in result.iter_mut().zip(to.iter()) {
+        *channel += (target - *channel) * t;
+    }
+    result
+}
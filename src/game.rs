@@ -0,0 +1,379 @@
+//! A game being played: the state with the keys that drive it, the animations it sets off
+//! and what gets printed and kept once it's over.
+
+use std::time::Duration;
+
+use winit::VirtualKeyCode;
+
+use state::{State, Visible};
+use state::template::Kind;
+use state::template::DeltaPos;
+use state::mode::Mode;
+use state::stopwatch;
+use state::puzzle::{self, Outcome};
+use state::event::{Event as GameEvent, Reason};
+use state::ruleset::DropStyle;
+use effects::Effects;
+use particles::Particles;
+use scene::Scene;
+use config::Config;
+use keys::Action;
+use records::{Records, Best, RECORDS_PATH};
+use replay;
+
+pub struct Game {
+    pub state: State,
+    /// Sideways action held down for the ruleset's own auto shift, and the ticks it's been held.
+    shift: Option<Action>,
+    shift_ticks: u32,
+    effects: Effects,
+    particles: Particles,
+    /// How the game went, shown over the board once it's over.
+    results: Vec<String>,
+}
+
+impl Game {
+    pub fn new(config: &Config) -> Result<Self, String> {
+        let mut state = State::new(config);
+        if config.mode == Mode::Puzzle {
+            let pack = puzzle::load(&config.puzzle_pack).map_err(|err| format!("Can't load puzzles: {}", err))?;
+            println!("Puzzle 1: {}", pack[0].name);
+            state.start_puzzles(pack);
+        }
+        if let Some(ref fumen) = config.fumen {
+            match state.load_fumen(fumen) {
+                Ok(()) => if !state.comment.is_empty() {
+                    println!("{}", state.comment);
+                },
+                Err(err) => eprintln!("Can't load fumen: {}", err),
+            }
+        }
+        if config.log_events {
+            state.subscribe(|event| println!("{:?}", event));
+        }
+        Ok(Game {
+            state: state,
+            shift: None,
+            shift_ticks: 0,
+            effects: Effects::new(config.animations, config.callouts),
+            particles: Particles::new(config.particles),
+            results: Vec::new(),
+        })
+    }
+
+    pub fn is_over(&self) -> bool {
+        self.state.is_gameover
+    }
+
+    pub fn scene(&self) -> Scene {
+        let mut scene = Scene::new(&self.state, &self.effects, &self.particles);
+        if self.state.is_gameover && !self.results.is_empty() {
+            scene.results(&self.results);
+        }
+        scene
+    }
+
+    /// Plays `key`, returning whether it's one of the game's own.
+    pub fn on_press(&mut self, key: VirtualKeyCode) -> bool {
+        if self.state.is_gameover {
+            return false;
+        }
+        let action = match self.state.config.keys.action(key) {
+            Some(action) => action,
+            None => return self.on_other_key(key),
+        };
+        self.state.press_key();
+        let is_sideways = action == Action::Left || action == Action::Right;
+        if is_sideways && self.state.ruleset.das.is_some() {
+            // the keyboard's repeat is ignored, the tick loop does the repeating
+            if self.shift == Some(action) {
+                return true;
+            }
+            self.shift = Some(action);
+            self.shift_ticks = 0;
+        }
+        if self.state.is_between_pieces() {
+            match action {
+                Action::Rotate => self.state.turn_on_entry(3),
+                Action::HalfTurn if self.state.ruleset.has_half_turn => self.state.turn_on_entry(2),
+                Action::Hold if self.state.ruleset.has_hold => self.state.hold_on_entry(),
+                _ => (),
+            }
+            return true;
+        }
+        match action {
+            Action::Left => self.shift_piece(-1),
+            Action::Right => self.shift_piece(1),
+            Action::SoftDrop => {
+                self.state.draw_piece(Visible::No);
+                let moved = self.state.move_piece(DeltaPos { dx: 0, dy: 1 });
+                self.state.draw_piece(Visible::Yes);
+                if moved.is_err() && self.state.ruleset.is_soft_drop_lock {
+                    self.lock_piece();
+                }
+            },
+            Action::Rotate => {
+                self.state.draw_piece(Visible::No);
+                let _ = self.state.rotate_piece();
+                self.state.draw_piece(Visible::Yes);
+            }
+            Action::HalfTurn if self.state.ruleset.has_half_turn => {
+                self.state.draw_piece(Visible::No);
+                let _ = self.state.rotate_piece_half();
+                self.state.draw_piece(Visible::Yes);
+            }
+            Action::Drop if self.state.ruleset.drop != DropStyle::None => {
+                let top = self.state.piece.pos.y;
+                self.state.draw_piece(Visible::No);
+                self.state.hard_drop();
+                self.state.draw_piece(Visible::Yes);
+                self.drop_trail(top);
+                if self.state.ruleset.drop == DropStyle::Hard {
+                    self.lock_piece();
+                }
+            }
+            Action::Hold if self.state.ruleset.has_hold => self.state.hold_piece(),
+            _ => (),
+        }
+        true
+    }
+
+    pub fn on_release(&mut self, key: VirtualKeyCode) {
+        if self.shift.is_some() && self.shift == self.state.config.keys.action(key) {
+            self.shift = None;
+        }
+    }
+
+    /// Keys that aren't for moving pieces: printing fumens, and in zen the practice keys.
+    fn on_other_key(&mut self, key: VirtualKeyCode) -> bool {
+        if self.state.config.mode == Mode::Zen && self.practice(key) {
+            return true;
+        }
+        match key {
+            VirtualKeyCode::E => println!("{}", self.state.board_fumen()),
+            VirtualKeyCode::F => println!("{}", self.state.game_fumen()),
+            _ => return false,
+        }
+        true
+    }
+
+    /// Leaves a trail behind the piece from row `top` down to where it is now.
+    fn drop_trail(&mut self, top: usize) {
+        let piece = self.state.piece;
+        if let Ok(cells) = piece.try_into(&self.state.main) {
+            let cells: Vec<(usize, usize)> = cells.iter().map(|pos| (pos.x, pos.y)).collect();
+            self.particles.trail(&cells, piece.pos.y - top, piece.color.into());
+        }
+    }
+
+    pub fn tick(&mut self) {
+        self.effects.tick();
+        self.particles.tick();
+        if !self.state.is_running() {
+            return;
+        }
+        self.state.tick();
+        if self.state.is_gameover {
+            return;
+        }
+        self.auto_shift();
+        if self.state.is_between_pieces() || !self.state.config.mode.has_gravity() {
+            return;
+        }
+        let mut is_blocked = false;
+        for _ in 0..self.state.timer.drops() {
+            self.state.draw_piece(Visible::No);
+            is_blocked = self.state.move_piece(DeltaPos { dx: 0, dy: 1 }).is_err();
+            self.state.draw_piece(Visible::Yes);
+            if is_blocked {
+                break;
+            }
+        }
+        if self.state.is_lock_due(is_blocked) {
+            self.lock_piece();
+        }
+    }
+
+    fn shift_piece(&mut self, dx: isize) {
+        self.state.draw_piece(Visible::No);
+        let _ = self.state.move_piece(DeltaPos { dx: dx, dy: 0 });
+        self.state.draw_piece(Visible::Yes);
+    }
+
+    /// Repeats the held sideways key once it's been down for the ruleset's delay. The delay
+    /// charges while the next piece is on its way, so that piece can come in already shifting.
+    fn auto_shift(&mut self) {
+        let (delay, repeat) = match (self.shift, self.state.ruleset.das) {
+            (Some(_), Some(das)) => das,
+            _ => return,
+        };
+        self.shift_ticks += 1;
+        if self.shift_ticks < delay || (self.shift_ticks - delay) % repeat != 0 {
+            return;
+        }
+        if self.state.is_between_pieces() {
+            return;
+        }
+        match self.shift {
+            Some(Action::Left) => self.shift_piece(-1),
+            Some(Action::Right) => self.shift_piece(1),
+            _ => (),
+        }
+    }
+
+    /// Zen-only keys: undo, redo, a fresh queue and picking the next piece.
+    /// Returns whether the key was one of them.
+    fn practice(&mut self, key: VirtualKeyCode) -> bool {
+        let kind = match key {
+            VirtualKeyCode::U => {
+                self.state.undo();
+                return true;
+            },
+            VirtualKeyCode::Y => {
+                self.state.redo();
+                return true;
+            },
+            VirtualKeyCode::R => {
+                self.state.reset_queue();
+                return true;
+            },
+            VirtualKeyCode::Key1 => Kind::I,
+            VirtualKeyCode::Key2 => Kind::T,
+            VirtualKeyCode::Key3 => Kind::O,
+            VirtualKeyCode::Key4 => Kind::J,
+            VirtualKeyCode::Key5 => Kind::L,
+            VirtualKeyCode::Key6 => Kind::S,
+            VirtualKeyCode::Key7 => Kind::Z,
+            _ => return false,
+        };
+        self.state.choose_next(kind);
+        true
+    }
+
+    fn lock_piece(&mut self) {
+        self.state.lock_piece();
+        self.handle_events();
+    }
+
+    pub fn handle_events(&mut self) {
+        for event in self.state.take_events() {
+            self.effects.on(&event);
+            self.particles.on(&event);
+            match event {
+                GameEvent::PerfectClear => println!("Perfect clear! +{}", self.state.config.perfect_clear_bonus),
+                GameEvent::GameOver { reason } => {
+                    self.report(reason);
+                    if self.state.config.save_replays {
+                        match replay::save(&mut self.state) {
+                            Ok(path) => println!("Replay saved to {}", path.display()),
+                            Err(err) => eprintln!("Can't save replay: {}", err),
+                        }
+                    }
+                },
+                _ => (),
+            }
+        }
+        // a puzzle solved or failed, by a lock or a hold with no room for the piece
+        if let Some(outcome) = self.state.puzzles.outcome.take() {
+            match outcome {
+                Outcome::Solved => println!("Solved!"),
+                Outcome::Failed => println!("Failed, try again"),
+            }
+            if let Some(puzzle) = self.state.puzzles.current() {
+                println!("Puzzle {}: {}", self.state.puzzles.index + 1, puzzle.name);
+            }
+        }
+    }
+
+    /// Prints how the game went and keeps the gist of it for the finish screen.
+    fn report(&mut self, reason: Reason) {
+        let state = &self.state;
+        if !state.is_complete {
+            println!("Game over: {}\nYour score: {}", reason, state.score);
+            self.results = vec!["GAME OVER".to_string(), reason.to_string(), format!("SCORE {}", state.score)];
+            match state.config.mode {
+                Mode::Marathon => {
+                    self.report_record(state.score as f64, Best::Highest, |score| format!("{}", score));
+                },
+                Mode::Survival => {
+                    let secs = stopwatch::seconds(state.stopwatch.elapsed());
+                    println!("Survived: {}\nLines: {}", show_time(secs), state.stats.lines);
+                    self.results[2] = format!("SURVIVED {}", show_time(secs));
+                    self.report_record(secs, Best::Highest, show_time);
+                },
+                _ => (),
+            }
+            return;
+        }
+        match state.config.mode {
+            Mode::Endless | Mode::Survival | Mode::Zen => (),
+            Mode::Puzzle => {
+                println!("All {} puzzles solved!", state.puzzles.pack.len());
+                self.results = vec!["ALL SOLVED".to_string()];
+            },
+            Mode::Sprint => {
+                let time = state.stopwatch.elapsed();
+                let secs = stopwatch::seconds(time);
+                let pps = state.stats.pieces as f64 / secs;
+                let kpp = state.stats.keys as f64 / state.stats.pieces as f64;
+                println!("Sprint {} finished\nTime: {}\nPPS: {:.2}\nKPP: {:.2}",
+                         state.config.sprint_lines, stopwatch::format(time), pps, kpp);
+                self.results = vec![
+                    format!("SPRINT {}", state.config.sprint_lines),
+                    stopwatch::format(time),
+                    format!("{:.2} PPS", pps),
+                    format!("{:.2} KPP", kpp),
+                ];
+                self.report_record(secs, Best::Lowest, show_time);
+            },
+            Mode::Ultra => {
+                let stats = &state.stats;
+                println!("Ultra finished\nScore: {}\nLines: {}\n\
+                          Singles: {}\nDoubles: {}\nTriples: {}\nTetrises: {}\nT-spins: {}\n\
+                          Perfect clears: {} of {} chances",
+                         state.score, stats.lines,
+                         stats.singles, stats.doubles, stats.triples, stats.tetrises, stats.tspins,
+                         stats.perfect_clears, stats.pc_chances);
+                self.results = vec!["ULTRA".to_string(), format!("SCORE {}", state.score), format!("{} LINES", stats.lines)];
+                self.report_record(state.score as f64, Best::Highest, |score| format!("{}", score));
+            },
+            Mode::Dig => {
+                let time = state.stopwatch.elapsed();
+                let secs = stopwatch::seconds(time);
+                let pps = state.stats.pieces as f64 / secs;
+                println!("Dig {} finished\nTime: {}\nPieces: {}\nPPS: {:.2}",
+                         state.config.dig_lines, stopwatch::format(time), state.stats.pieces, pps);
+                self.results = vec![
+                    format!("DIG {}", state.config.dig_lines),
+                    stopwatch::format(time),
+                    format!("{:.2} PPS", pps),
+                ];
+                self.report_record(secs, Best::Lowest, show_time);
+            },
+            Mode::Marathon => {
+                println!("Marathon complete!\nScore: {}\nLines: {}\nTime: {}",
+                         state.score, state.stats.lines, stopwatch::format(state.stopwatch.elapsed()));
+                self.results = vec!["MARATHON".to_string(), format!("SCORE {}", state.score), format!("{} LINES", state.stats.lines)];
+                self.report_record(state.score as f64, Best::Highest, |score| format!("{}", score));
+            },
+        }
+    }
+
+    fn report_record<F: Fn(f64) -> String>(&mut self, value: f64, best: Best, show: F) {
+        if let Some(key) = self.state.record_key() {
+            let mut records = Records::load(RECORDS_PATH);
+            let previous = records.get(&key);
+            if records.submit(&key, value, best) {
+                println!("New personal best!");
+                self.results.push("NEW BEST!".to_string());
+            }
+            else if let Some(previous) = previous {
+                println!("Personal best: {}", show(previous));
+            }
+        }
+    }
+}
+
+pub fn show_time(secs: f64) -> String {
+    stopwatch::format(Duration::from_millis((secs * 1000.0) as u64))
+}
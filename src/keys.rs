@@ -0,0 +1,117 @@
+//! Keys the game is played with, set in the config as `key_<action> = <key>` and changed
+//! in the key menu.
+
+use winit::VirtualKeyCode as Key;
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum Action {
+    Left,
+    Right,
+    SoftDrop,
+    Rotate,
+    HalfTurn,
+    Drop,
+    Hold,
+}
+
+pub const ACTIONS: [Action; 7] = [
+    Action::Left, Action::Right, Action::SoftDrop, Action::Rotate,
+    Action::HalfTurn, Action::Drop, Action::Hold,
+];
+
+/// Keys that can be bound, by the names the config and the menus use.
+const NAMES: [(Key, &'static str); 76] = [
+    (Key::A, "A"), (Key::B, "B"), (Key::C, "C"), (Key::D, "D"), (Key::E, "E"), (Key::F, "F"),
+    (Key::G, "G"), (Key::H, "H"), (Key::I, "I"), (Key::J, "J"), (Key::K, "K"), (Key::L, "L"),
+    (Key::M, "M"), (Key::N, "N"), (Key::O, "O"), (Key::P, "P"), (Key::Q, "Q"), (Key::R, "R"),
+    (Key::S, "S"), (Key::T, "T"), (Key::U, "U"), (Key::V, "V"), (Key::W, "W"), (Key::X, "X"),
+    (Key::Y, "Y"), (Key::Z, "Z"),
+    (Key::Key0, "0"), (Key::Key1, "1"), (Key::Key2, "2"), (Key::Key3, "3"), (Key::Key4, "4"),
+    (Key::Key5, "5"), (Key::Key6, "6"), (Key::Key7, "7"), (Key::Key8, "8"), (Key::Key9, "9"),
+    (Key::Left, "Left"), (Key::Right, "Right"), (Key::Up, "Up"), (Key::Down, "Down"),
+    (Key::Space, "Space"), (Key::Return, "Return"), (Key::Tab, "Tab"), (Key::Back, "Back"),
+    (Key::LShift, "LShift"), (Key::RShift, "RShift"), (Key::LControl, "LControl"),
+    (Key::RControl, "RControl"), (Key::LAlt, "LAlt"), (Key::RAlt, "RAlt"),
+    (Key::Comma, "Comma"), (Key::Period, "Period"), (Key::Slash, "Slash"),
+    (Key::Semicolon, "Semicolon"), (Key::Apostrophe, "Apostrophe"), (Key::Minus, "Minus"),
+    (Key::Equals, "Equals"), (Key::LBracket, "LBracket"), (Key::RBracket, "RBracket"),
+    (Key::Backslash, "Backslash"),
+    (Key::Insert, "Insert"), (Key::Delete, "Delete"), (Key::Home, "Home"), (Key::End, "End"),
+    (Key::PageUp, "PageUp"), (Key::PageDown, "PageDown"),
+    (Key::Numpad0, "Numpad0"), (Key::Numpad1, "Numpad1"), (Key::Numpad2, "Numpad2"),
+    (Key::Numpad3, "Numpad3"), (Key::Numpad4, "Numpad4"), (Key::Numpad5, "Numpad5"),
+    (Key::Numpad6, "Numpad6"), (Key::Numpad7, "Numpad7"), (Key::Numpad8, "Numpad8"),
+    (Key::Numpad9, "Numpad9"),
+];
+
+impl Action {
+    /// Name in the config, after `key_`.
+    pub fn name(&self) -> &'static str {
+        match *self {
+            Action::Left => "left",
+            Action::Right => "right",
+            Action::SoftDrop => "soft_drop",
+            Action::Rotate => "rotate",
+            Action::HalfTurn => "half_turn",
+            Action::Drop => "drop",
+            Action::Hold => "hold",
+        }
+    }
+
+    /// Name in the key menu.
+    pub fn label(&self) -> &'static str {
+        match *self {
+            Action::Left => "LEFT",
+            Action::Right => "RIGHT",
+            Action::SoftDrop => "SOFT DROP",
+            Action::Rotate => "ROTATE",
+            Action::HalfTurn => "HALF TURN",
+            Action::Drop => "DROP",
+            Action::Hold => "HOLD",
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug)]
+pub struct Bindings {
+    keys: [Key; 7],
+}
+
+impl Default for Bindings {
+    fn default() -> Self {
+        Bindings {
+            keys: [Key::Left, Key::Right, Key::Down, Key::Up, Key::A, Key::Space, Key::C],
+        }
+    }
+}
+
+impl Bindings {
+    pub fn key(&self, action: Action) -> Key {
+        self.keys[action as usize]
+    }
+
+    pub fn action(&self, key: Key) -> Option<Action> {
+        ACTIONS.iter().cloned().find(|&action| self.key(action) == key)
+    }
+
+    /// Binds `key` to `action`, handing the action's old key to whichever action had `key`.
+    pub fn set(&mut self, action: Action, key: Key) {
+        if let Some(other) = self.action(key) {
+            self.keys[other as usize] = self.key(action);
+        }
+        self.keys[action as usize] = key;
+    }
+}
+
+/// Only keys with a name can be bound; Escape has none, it always goes back.
+pub fn is_bindable(key: Key) -> bool {
+    NAMES.iter().any(|&(named, _)| named == key)
+}
+
+pub fn parse(name: &str) -> Option<Key> {
+    NAMES.iter().find(|&&(_, known)| known.eq_ignore_ascii_case(name)).map(|&(key, _)| key)
+}
+
+pub fn name(key: Key) -> &'static str {
+    NAMES.iter().find(|&&(named, _)| named == key).map(|&(_, name)| name).unwrap_or("?")
+}
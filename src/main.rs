@@ -1,24 +1,110 @@
 #[macro_use]
 extern crate gfx;
 extern crate gfx_app;
+extern crate gfx_device_gl;
+extern crate gfx_window_glutin;
+extern crate glutin;
 extern crate winit;
 extern crate rand;
+extern crate image;
 
 mod app;
+mod config;
+mod effects;
+mod font;
+mod game;
+mod keys;
+mod menu;
+mod particles;
+mod raster;
+mod records;
+mod renderer;
+mod replay;
+mod scene;
+mod skin;
 mod state;
 
 const BOX_SIZE: usize = 20;
+/// Smallest box the window can be shrunk to, in pixels.
+const MIN_BOX_SIZE: usize = 8;
 
 fn main() {
-    use gfx_app::Application;
     use winit::WindowBuilder;
 
-    let width = ((state::MAIN_WIDTH + state::PREVIEW_WIDTH) * BOX_SIZE) as u32;
-    let height = (state::HEIGHT * BOX_SIZE) as u32;
-    let wb = WindowBuilder::new()
-        .with_min_dimensions(width, height)
-        .with_max_dimensions(width, height)
+    let config = config::Config::load(config::CONFIG_PATH);
+    // these save pictures and quit, no window needed:
+    // `--png <path>` the board the game would start on,
+    // `--replay <fumen> <path> [pages a second] [box pixels]` a game as a GIF or PNG frames
+    let args: Vec<String> = std::env::args().collect();
+    if args.len() == 3 && args[1] == "--png" {
+        save_board(&config, &args[2]);
+        return;
+    }
+    if args.len() >= 4 && args.len() <= 6 && args[1] == "--replay" {
+        save_replay(&config, &args[2..]);
+        return;
+    }
+    let columns = state::HOLD_WIDTH + state::MAIN_WIDTH + state::PREVIEW_WIDTH;
+    let rows = state::VISIBLE_HEIGHT;
+    let monitor = winit::get_primary_monitor();
+    let box_size = BOX_SIZE as f64 * config.window_scale.unwrap_or_else(|| hidpi_factor(&monitor));
+    let mut wb = WindowBuilder::new()
+        .with_dimensions((columns as f64 * box_size) as u32, (rows as f64 * box_size) as u32)
+        .with_min_dimensions((columns * MIN_BOX_SIZE) as u32, (rows * MIN_BOX_SIZE) as u32)
         .with_title("Tetris!");
+    if config.fullscreen {
+        wb = wb.with_fullscreen(monitor);
+    }
 
-    app::App::launch_default(wb);
+    app::launch(wb);
+}
+
+/// winit only knows a HiDPI factor once there's a window, so the monitor's is guessed
+/// from its height: one for every 1080 rows of pixels.
+fn hidpi_factor(monitor: &winit::MonitorId) -> f64 {
+    let (_, height) = monitor.get_dimensions();
+    std::cmp::max(1, height / 1080) as f64
+}
+
+fn save_board(config: &config::Config, path: &str) {
+    let mut state = state::State::new(config);
+    if let Some(ref fumen) = config.fumen {
+        if let Err(err) = state.load_fumen(fumen) {
+            eprintln!("Can't load fumen: {}", err);
+            std::process::exit(1);
+        }
+    }
+    match raster::render_board_png(&state, path) {
+        Ok(()) => println!("Saved {}", path),
+        Err(err) => {
+            eprintln!("Can't save {}: {}", path, err);
+            std::process::exit(1);
+        },
+    }
+}
+
+fn save_replay(config: &config::Config, args: &[String]) {
+    let speed = match args.get(2).map(|arg| arg.parse::<f64>()) {
+        None => replay::DEFAULT_SPEED,
+        Some(Ok(speed)) if speed > 0.0 && speed <= 100.0 => speed,
+        _ => {
+            eprintln!("Pages a second should be a number above 0, up to 100");
+            std::process::exit(1);
+        },
+    };
+    let box_size = match args.get(3).map(|arg| arg.parse::<usize>()) {
+        None => BOX_SIZE,
+        Some(Ok(size)) if size >= 1 && size <= 100 => size,
+        _ => {
+            eprintln!("Box pixels should be a whole number from 1 to 100");
+            std::process::exit(1);
+        },
+    };
+    match replay::export(config, &args[0], &args[1], speed, box_size) {
+        Ok(frames) => println!("Saved {} frames to {}", frames, args[1]),
+        Err(err) => {
+            eprintln!("Can't export replay: {}", err);
+            std::process::exit(1);
+        },
+    }
 }
@@ -0,0 +1,263 @@
+//! The screens around a game: the main menu, picking a mode, settings, keys, high scores
+//! and replays, each drawn as text with the same quads as the board.
+
+use std::fs;
+use std::path::PathBuf;
+use std::time::SystemTime;
+
+use config::Config;
+use game::{self, Game};
+use keys::{self, ACTIONS};
+use records::{Records, RECORDS_PATH};
+use replay::{self, Viewer};
+use scene::Scene;
+use skin::{Skin, Style};
+use state::mode::MODES;
+use state::ruleset::RULES;
+use winit::VirtualKeyCode;
+
+/// Where the player is, and what's on each screen that isn't in the config.
+pub enum Screen {
+    /// With the item picked.
+    Main(usize),
+    Modes(usize),
+    Settings(usize),
+    /// `is_binding` while waiting for the key to give the picked action.
+    Keys { selected: usize, is_binding: bool },
+    /// With the first line in view.
+    Scores(usize),
+    Replays { selected: usize, files: Vec<PathBuf> },
+    Watching(Viewer),
+    Playing(Game),
+    /// Left from the main menu; the window closes before it's drawn.
+    Quit,
+}
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum Item {
+    Play,
+    Modes,
+    Settings,
+    Keys,
+    Scores,
+    Replays,
+    Quit,
+}
+
+pub const ITEMS: [Item; 7] = [
+    Item::Play, Item::Modes, Item::Settings, Item::Keys, Item::Scores, Item::Replays, Item::Quit,
+];
+
+impl Item {
+    fn label(&self) -> &'static str {
+        match *self {
+            Item::Play => "PLAY",
+            Item::Modes => "MODES",
+            Item::Settings => "SETTINGS",
+            Item::Keys => "KEYS",
+            Item::Scores => "HIGH SCORES",
+            Item::Replays => "REPLAYS",
+            Item::Quit => "QUIT",
+        }
+    }
+}
+
+/// Config keys the settings screen changes, left and right going through their values.
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum Setting {
+    Rules,
+    Preview,
+    StartLevel,
+    Skin,
+    Grid,
+    Animations,
+    Callouts,
+    Particles,
+    SaveReplays,
+}
+
+pub const SETTINGS: [Setting; 9] = [
+    Setting::Rules, Setting::Preview, Setting::StartLevel, Setting::Skin, Setting::Grid,
+    Setting::Animations, Setting::Callouts, Setting::Particles, Setting::SaveReplays,
+];
+
+impl Setting {
+    fn label(&self) -> &'static str {
+        match *self {
+            Setting::Rules => "RULESET",
+            Setting::Preview => "PREVIEW",
+            Setting::StartLevel => "NES LEVEL",
+            Setting::Skin => "SKIN",
+            Setting::Grid => "GRID",
+            Setting::Animations => "ANIMATIONS",
+            Setting::Callouts => "CALLOUTS",
+            Setting::Particles => "PARTICLES",
+            Setting::SaveReplays => "SAVE REPLAYS",
+        }
+    }
+
+    /// The config key and its value as the file has it.
+    pub fn value(&self, config: &Config) -> (&'static str, String) {
+        match *self {
+            Setting::Rules => ("ruleset", config.rules.name().to_string()),
+            Setting::Preview => ("preview", config.preview.to_string()),
+            Setting::StartLevel => ("start_level", config.start_level.to_string()),
+            Setting::Skin => ("skin", config.skin.name().to_string()),
+            Setting::Grid => ("grid", config.grid.to_string()),
+            Setting::Animations => ("animations", config.animations.to_string()),
+            Setting::Callouts => ("callouts", config.callouts.to_string()),
+            Setting::Particles => ("particles", config.particles.to_string()),
+            Setting::SaveReplays => ("save_replays", config.save_replays.to_string()),
+        }
+    }
+
+    /// Moves the setting `step` values on, wrapping round, and puts it in effect on `skin`
+    /// straight away; the rest wait for the next game.
+    pub fn change(&self, config: &mut Config, skin: &mut Skin, step: isize) {
+        match *self {
+            Setting::Rules => config.rules = RULES[turn(RULES.iter().position(|&r| r == config.rules), RULES.len(), step)],
+            Setting::Preview => config.preview = turn(Some(config.preview), ::config::MAX_PREVIEW + 1, step),
+            Setting::StartLevel => config.start_level = turn(Some(config.start_level as usize), 30, step) as u32,
+            Setting::Skin => {
+                let styles: Vec<Style> = [Style::Flat, Style::Bevel, Style::Outline, Style::Atlas].iter()
+                    .cloned()
+                    .filter(|&style| style != Style::Atlas || skin.atlas.is_some())
+                    .collect();
+                config.skin = styles[turn(styles.iter().position(|&s| s == config.skin), styles.len(), step)];
+                skin.style = config.skin;
+            },
+            Setting::Grid => {
+                config.grid = !config.grid;
+                skin.has_grid = config.grid;
+            },
+            Setting::Animations => config.animations = !config.animations,
+            Setting::Callouts => config.callouts = !config.callouts,
+            Setting::Particles => config.particles = !config.particles,
+            Setting::SaveReplays => config.save_replays = !config.save_replays,
+        }
+    }
+}
+
+/// Index `step` on from `index` in a list of `len`, wrapping round.
+fn turn(index: Option<usize>, len: usize, step: isize) -> usize {
+    let index = index.unwrap_or(0) as isize;
+    ((index + step) % len as isize + len as isize) as usize % len
+}
+
+/// `selected` moved up or down a list of `len` by the arrow keys.
+pub fn select(selected: usize, len: usize, key: VirtualKeyCode) -> usize {
+    match key {
+        VirtualKeyCode::Up => turn(Some(selected), len, -1),
+        VirtualKeyCode::Down => turn(Some(selected), len, 1),
+        _ => selected,
+    }
+}
+
+/// Saved replays, the latest first.
+pub fn replay_files() -> Vec<PathBuf> {
+    let mut files: Vec<(SystemTime, PathBuf)> = match fs::read_dir(replay::REPLAYS_PATH) {
+        Ok(entries) => entries
+            .filter_map(|entry| entry.ok())
+            .map(|entry| entry.path())
+            .filter(|path| path.extension().map_or(false, |extension| extension == "txt"))
+            .map(|path| (modified(&path), path))
+            .collect(),
+        Err(_) => Vec::new(),
+    };
+    files.sort_by(|a, b| b.0.cmp(&a.0));
+    files.into_iter().map(|(_, path)| path).collect()
+}
+
+fn modified(path: &PathBuf) -> SystemTime {
+    fs::metadata(path).and_then(|metadata| metadata.modified()).unwrap_or(SystemTime::UNIX_EPOCH)
+}
+
+/// The screen as it's drawn.
+pub fn scene(screen: &Screen, config: &Config) -> Scene {
+    match *screen {
+        Screen::Playing(ref game) => game.scene(),
+        Screen::Watching(ref viewer) => viewer.scene(),
+        Screen::Main(selected) => {
+            let lines: Vec<(String, String)> = ITEMS.iter().map(|item| (item.label().to_string(), String::new())).collect();
+            Scene::menu("TETRIS", &lines, Some(selected))
+        },
+        Screen::Modes(selected) => {
+            let lines: Vec<(String, String)> = MODES.iter()
+                .map(|mode| {
+                    let mark = if *mode == config.mode { "-" } else { "" };
+                    (mode.name().to_string(), mark.to_string())
+                })
+                .collect();
+            Scene::menu("MODES", &lines, Some(selected))
+        },
+        Screen::Settings(selected) => {
+            let lines: Vec<(String, String)> = SETTINGS.iter()
+                .map(|setting| {
+                    let value = setting.value(config).1.replace('_', " ");
+                    let value = match value.as_str() {
+                        "true" => "ON".to_string(),
+                        "false" => "OFF".to_string(),
+                        _ => value,
+                    };
+                    (setting.label().to_string(), value)
+                })
+                .collect();
+            Scene::menu("SETTINGS", &lines, Some(selected))
+        },
+        Screen::Keys { selected, is_binding } => {
+            let mut lines: Vec<(String, String)> = ACTIONS.iter()
+                .enumerate()
+                .map(|(i, &action)| {
+                    let key = if is_binding && i == selected { "..." } else { keys::name(config.keys.key(action)) };
+                    (action.label().to_string(), key.to_string())
+                })
+                .collect();
+            lines.push(("DEFAULTS".to_string(), String::new()));
+            Scene::menu("KEYS", &lines, Some(selected))
+        },
+        Screen::Scores(first) => {
+            let lines = score_lines();
+            let lines = if lines.is_empty() { vec![("NONE YET".to_string(), String::new())] } else { lines };
+            Scene::menu("HIGH SCORES", &lines[first.min(lines.len() - 1)..], None)
+        },
+        Screen::Replays { selected, ref files } => {
+            let lines: Vec<(String, String)> = files.iter().map(|path| replay_line(path)).collect();
+            if lines.is_empty() {
+                Scene::menu("REPLAYS", &[("NONE YET".to_string(), String::new())], None)
+            }
+            else {
+                Scene::menu("REPLAYS", &lines, Some(selected))
+            }
+        },
+        Screen::Quit => Scene::menu("", &[], None),
+    }
+}
+
+/// Personal bests, each by its mode and length, in times or points as the mode keeps them.
+pub fn score_lines() -> Vec<(String, String)> {
+    let records = Records::load(RECORDS_PATH);
+    records.iter()
+        .map(|(key, &value)| {
+            let is_time = key.starts_with("sprint") || key.starts_with("dig") || key == "survival";
+            let value = if is_time { game::show_time(value) } else { format!("{}", value) };
+            // `sprint40` reads as `SPRINT 40`
+            let split = key.find(|c: char| c.is_digit(10)).unwrap_or(key.len());
+            let label = format!("{} {}", &key[..split], &key[split..]).replace('_', " ");
+            (label.trim().to_string(), value)
+        })
+        .collect()
+}
+
+/// A replay by its mode and how long ago it was played.
+fn replay_line(path: &PathBuf) -> (String, String) {
+    let name = path.file_stem().map(|stem| stem.to_string_lossy().into_owned()).unwrap_or_default();
+    let mode = name.split('-').next().unwrap_or("").to_string();
+    let secs = SystemTime::now().duration_since(modified(path)).map(|age| age.as_secs()).unwrap_or(0);
+    let age = match secs {
+        secs if secs < 60 => "NOW".to_string(),
+        secs if secs < 60 * 60 => format!("{}M AGO", secs / 60),
+        secs if secs < 24 * 60 * 60 => format!("{}H AGO", secs / 60 / 60),
+        secs => format!("{}D AGO", secs / 24 / 60 / 60),
+    };
+    (mode, age)
+}
@@ -0,0 +1,104 @@
+//! Specks thrown off by hard drops and line clears, moved a tick at a time on the CPU
+//! and drawn together as instanced quads.
+
+use rand;
+use rand::Rng;
+
+use state::event::Event;
+use state::MAIN_WIDTH;
+
+/// Particles alive at once, the size of the instance buffer; more are dropped.
+pub const MAX_PARTICLES: usize = 1024;
+/// Downward pull in rows per tick, per tick.
+const GRAVITY: f32 = 0.01;
+/// Size of a new particle, in boxes; they shrink away as they age.
+const SIZE: f32 = 0.3;
+const BURST_PER_CELL: usize = 3;
+const BURST_TICKS: u32 = 40;
+const TRAIL_PER_ROW: usize = 1;
+const TRAIL_TICKS: u32 = 16;
+
+pub struct Particle {
+    /// Column and row on the main board, fractions included.
+    pub pos: [f32; 2],
+    velocity: [f32; 2],
+    /// Ticks left, out of `lifetime`.
+    life: u32,
+    lifetime: u32,
+    pub color: [f32; 3],
+}
+
+impl Particle {
+    pub fn size(&self) -> f32 {
+        SIZE * self.life as f32 / self.lifetime as f32
+    }
+}
+
+pub struct Particles {
+    is_enabled: bool,
+    list: Vec<Particle>,
+}
+
+impl Particles {
+    pub fn new(is_enabled: bool) -> Self {
+        Particles {
+            is_enabled: is_enabled,
+            list: Vec::with_capacity(MAX_PARTICLES),
+        }
+    }
+
+    pub fn iter<'a>(&'a self) -> ::std::slice::Iter<'a, Particle> {
+        self.list.iter()
+    }
+
+    pub fn tick(&mut self) {
+        for particle in self.list.iter_mut() {
+            particle.velocity[1] += GRAVITY;
+            particle.pos[0] += particle.velocity[0];
+            particle.pos[1] += particle.velocity[1];
+            particle.life -= 1;
+        }
+        self.list.retain(|particle| particle.life > 0);
+    }
+
+    /// Bursts out of every cell of the cleared rows, in the colours the cells had.
+    pub fn on(&mut self, event: &Event) {
+        if let Event::LinesCleared { ref rows, ref cells, .. } = *event {
+            let mut rng = rand::thread_rng();
+            for (i, &row) in rows.iter().enumerate() {
+                for column in 0..MAIN_WIDTH {
+                    let color = cells[i * MAIN_WIDTH + column].into();
+                    for _ in 0..BURST_PER_CELL {
+                        let velocity = [rng.gen_range(-0.15, 0.15), rng.gen_range(-0.25, 0.0)];
+                        self.add([column as f32, row as f32], velocity, BURST_TICKS, color);
+                    }
+                }
+            }
+        }
+    }
+
+    /// Streaks left behind `cells` that just dropped `rows` rows.
+    pub fn trail(&mut self, cells: &[(usize, usize)], rows: usize, color: [f32; 3]) {
+        let mut rng = rand::thread_rng();
+        for &(column, row) in cells {
+            for _ in 0..rows * TRAIL_PER_ROW {
+                let pos = [column as f32 + rng.gen_range(-0.4, 0.4), row as f32 - rng.gen_range(0.0, rows as f32)];
+                let velocity = [0.0, rng.gen_range(-0.05, -0.01)];
+                self.add(pos, velocity, TRAIL_TICKS, color);
+            }
+        }
+    }
+
+    fn add(&mut self, pos: [f32; 2], velocity: [f32; 2], lifetime: u32, color: [f32; 3]) {
+        if !self.is_enabled || self.list.len() >= MAX_PARTICLES {
+            return;
+        }
+        self.list.push(Particle {
+            pos: pos,
+            velocity: velocity,
+            life: lifetime,
+            lifetime: lifetime,
+            color: color,
+        });
+    }
+}
@@ -0,0 +1,169 @@
+//! Draws a `Scene` into memory on the CPU, the way the shaders would, so boards can be
+//! saved as PNG without a window or a GPU: screenshots, tests and tools alike.
+
+use image;
+
+use effects::Effects;
+use particles::Particles;
+use scene::{Scene, Quad, BACKGROUND};
+use skin::{Skin, Atlas, Shade};
+use state::State;
+use BOX_SIZE;
+
+// widths as fractions of half a box, as in the fragment shader
+const BEVEL_WIDTH: f32 = 0.25;
+const OUTLINE_WIDTH: f32 = 0.2;
+const GRID_WIDTH: f32 = 0.1;
+
+/// RGBA pixels, a row at a time from the top.
+pub struct Image {
+    pub width: usize,
+    pub height: usize,
+    pub pixels: Vec<u8>,
+}
+
+impl Image {
+    pub fn save_png(&self, path: &str) -> Result<(), String> {
+        image::save_buffer(path, &self.pixels, self.width as u32, self.height as u32, image::ColorType::Rgba8)
+            .map_err(|err| err.to_string())
+    }
+}
+
+/// Saves the board of `state` as it would be drawn, with no animations, in the skin its
+/// config names.
+pub fn render_board_png(state: &State, path: &str) -> Result<(), String> {
+    let scene = Scene::new(state, &Effects::new(false, false), &Particles::new(false));
+    render(&scene, &Skin::new(&state.config), BOX_SIZE).save_png(path)
+}
+
+/// `scene` with boxes of `box_size` pixels.
+pub fn render(scene: &Scene, skin: &Skin, box_size: usize) -> Image {
+    let mut image = Image {
+        width: scene.width * box_size,
+        height: scene.height * box_size,
+        pixels: Vec::with_capacity(scene.width * box_size * scene.height * box_size * 4),
+    };
+    for _ in 0..image.width * image.height {
+        image.pixels.extend(to_bytes(BACKGROUND).iter());
+    }
+    for quad in scene.under.iter().chain(scene.board.iter()).chain(scene.over.iter()) {
+        fill(&mut image, quad, skin, box_size as f32);
+    }
+    image
+}
+
+fn fill(image: &mut Image, quad: &Quad, skin: &Skin, box_size: f32) {
+    let half = quad.size * box_size / 2.0;
+    if half <= 0.0 {
+        return;
+    }
+    let center = [(quad.center[0] + 0.5) * box_size, (quad.center[1] + 0.5) * box_size];
+    // pixels whose middles are inside the quad
+    let span = |middle: f32, end: usize| {
+        let first = (middle - half).round().max(0.0) as usize;
+        let last = ((middle + half).round().max(0.0) as usize).min(end);
+        first..last
+    };
+    let (shade, color, uv) = skin.shade(quad);
+    for y in span(center[1], image.height) {
+        for x in span(center[0], image.width) {
+            // -1 to 1 across the quad, up the way the shader has it
+            let pos = [(x as f32 + 0.5 - center[0]) / half, (center[1] - y as f32 - 0.5) / half];
+            let color = shade_pixel(shade, color, uv, pos, &skin.atlas);
+            let i = (y * image.width + x) * 4;
+            image.pixels[i..i + 4].copy_from_slice(&to_bytes(color));
+        }
+    }
+}
+
+/// What the fragment shader gives for `pos` in a quad.
+fn shade_pixel(shade: Shade, color: [f32; 3], uv: [f32; 4], pos: [f32; 2], atlas: &Option<Atlas>) -> [f32; 3] {
+    let inside = [1.0 - pos[0].abs(), 1.0 - pos[1].abs()];
+    let edge = inside[0].min(inside[1]);
+    match shade {
+        Shade::Bevel if edge < BEVEL_WIDTH => {
+            // lit from the top left
+            let is_lit = if inside[0] < inside[1] { pos[0] < 0.0 } else { pos[1] > 0.0 };
+            if is_lit { each(color, |c| c + (1.0 - c) * 0.4) } else { each(color, |c| c * 0.5) }
+        },
+        Shade::Outline if edge < OUTLINE_WIDTH => each(color, |c| c * 0.4),
+        Shade::Atlas => match *atlas {
+            Some(ref atlas) => {
+                let u = uv[0] + (pos[0] * 0.5 + 0.5) * uv[2];
+                let v = uv[1] + (-pos[1] * 0.5 + 0.5) * uv[3];
+                let texel = texel(atlas, u, v);
+                [color[0] * texel[0], color[1] * texel[1], color[2] * texel[2]]
+            },
+            None => color,
+        },
+        Shade::Grid if edge < GRID_WIDTH => each(color, |c| c + 0.08),
+        _ => color,
+    }
+}
+
+/// The atlas pixel nearest `u` and `v`, as the sampler picks them.
+fn texel(atlas: &Atlas, u: f32, v: f32) -> [f32; 3] {
+    let x = ((u * atlas.width as f32) as usize).min(atlas.width as usize - 1);
+    let y = ((v * atlas.height as f32) as usize).min(atlas.height as usize - 1);
+    let i = (y * atlas.width as usize + x) * 4;
+    [
+        atlas.pixels[i] as f32 / 255.0,
+        atlas.pixels[i + 1] as f32 / 255.0,
+        atlas.pixels[i + 2] as f32 / 255.0,
+    ]
+}
+
+fn to_bytes(color: [f32; 3]) -> [u8; 4] {
+    let byte = |c: f32| (c.max(0.0).min(1.0) * 255.0).round() as u8;
+    [byte(color[0]), byte(color[1]), byte(color[2]), 255]
+}
+
+fn each<F: Fn(f32) -> f32>(color: [f32; 3], f: F) -> [f32; 3] {
+    [f(color[0]), f(color[1]), f(color[2])]
+}
+
+#[cfg(test)]
+mod tests {
+    use std::env;
+    use std::fs;
+
+    use image;
+
+    use config::Config;
+    use skin::Style;
+    use state::{State, HOLD_WIDTH, VISIBLE_HEIGHT};
+    use state::color::{self, Color};
+    use BOX_SIZE;
+    use super::{render_board_png, to_bytes};
+
+    #[test]
+    fn saves_a_board_as_drawn() {
+        let mut config = Config::default();
+        config.skin = Style::Bevel;
+        let mut state = State::new(&config);
+        // a 6 by 4 block of garbage and a T on the floor at columns 6 to 8
+        state.load_fumen("v115@9gF8DeF8DeF8DeF8Ne1RJ").unwrap();
+        let path = env::temp_dir().join("tetris-raster-test.png");
+        render_board_png(&state, path.to_str().unwrap()).unwrap();
+        let image = image::open(&path).unwrap().to_rgba8();
+        fs::remove_file(&path).unwrap();
+
+        let (width, height) = image.dimensions();
+        assert_eq!((width as usize, height as usize), (18 * BOX_SIZE, VISIBLE_HEIGHT * BOX_SIZE));
+        let pixels = image.into_raw();
+        let pixel = |x: usize, y: usize| {
+            let i = (y * width as usize + x) * 4;
+            [pixels[i], pixels[i + 1], pixels[i + 2], pixels[i + 3]]
+        };
+        // middle of a box, and its top left corner where the bevel catches the light
+        let middle = |column: usize, row: usize| pixel(column * BOX_SIZE + BOX_SIZE / 2, row * BOX_SIZE + BOX_SIZE / 2);
+        let bottom = VISIBLE_HEIGHT - 1;
+        assert_eq!(middle(0, 0), to_bytes(color::PREVIEW.into()));
+        assert_eq!(middle(HOLD_WIDTH, bottom), to_bytes(color::GARBAGE.into()));
+        assert_eq!(middle(HOLD_WIDTH + 7, bottom), to_bytes(color::MAGENTA.into()));
+        assert_eq!(middle(HOLD_WIDTH + 7, bottom - 1), to_bytes(color::MAGENTA.into()));
+        assert_eq!(middle(HOLD_WIDTH + 9, bottom), to_bytes(Color::default().into()));
+        let lit = to_bytes([0.8 + 0.2 * 0.4, 0.4, 0.8 + 0.2 * 0.4]);
+        assert_eq!(pixel((HOLD_WIDTH + 7) * BOX_SIZE, bottom * BOX_SIZE), lit);
+    }
+}
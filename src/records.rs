@@ -0,0 +1,71 @@
+use std::collections::BTreeMap;
+use std::collections::btree_map;
+use std::fs::File;
+use std::io::{self, BufRead, BufReader, Write};
+use std::path::{Path, PathBuf};
+
+pub const RECORDS_PATH: &'static str = "records.txt";
+
+pub enum Best {
+    Lowest,
+    Highest,
+}
+
+/// Personal bests, kept as `key = value` lines next to the config.
+pub struct Records {
+    path: PathBuf,
+    entries: BTreeMap<String, f64>,
+}
+
+impl Records {
+    pub fn load<P: AsRef<Path>>(path: P) -> Self {
+        let mut entries = BTreeMap::new();
+        if let Ok(file) = File::open(path.as_ref()) {
+            for line in BufReader::new(file).lines().filter_map(|line| line.ok()) {
+                let mut parts = line.splitn(2, '=').map(|part| part.trim());
+                if let (Some(key), Some(value)) = (parts.next(), parts.next()) {
+                    if let Ok(value) = value.parse() {
+                        entries.insert(key.to_string(), value);
+                    }
+                }
+            }
+        }
+        Records {
+            path: path.as_ref().to_path_buf(),
+            entries: entries,
+        }
+    }
+
+    pub fn get(&self, key: &str) -> Option<f64> {
+        self.entries.get(key).cloned()
+    }
+
+    /// Every record, by key.
+    pub fn iter<'a>(&'a self) -> btree_map::Iter<'a, String, f64> {
+        self.entries.iter()
+    }
+
+    /// Stores `value` if it beats the current record and returns whether it did.
+    pub fn submit(&mut self, key: &str, value: f64, best: Best) -> bool {
+        let is_better = match (self.get(key), best) {
+            (None, _) => true,
+            (Some(old), Best::Lowest) => value < old,
+            (Some(old), Best::Highest) => value > old,
+        };
+        if is_better {
+            self.entries.insert(key.to_string(), value);
+            if let Err(err) = self.save() {
+                eprintln!("records: can't save {}: {}", self.path.display(), err);
+            }
+        }
+        is_better
+    }
+
+    fn save(&self) -> io::Result<()> {
+        let mut file = File::create(&self.path)?;
+        for (key, value) in self.entries.iter() {
+            writeln!(file, "{} = {}", key, value)?;
+        }
+        Ok(())
+    }
+}
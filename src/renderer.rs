@@ -0,0 +1,231 @@
+//! Draws a `Scene` with instanced quads: the board in one call out of a buffer that only
+//! takes the cells that changed since the last frame, and a call each for what goes
+//! under and over it. Boxes stay square whatever the window's shape, with the spare room
+//! left blank around them. Blocks and empty cells are shaded as the skin has them.
+
+use gfx;
+use gfx::Bundle;
+use gfx::handle::Buffer;
+use gfx::texture;
+use gfx_app;
+use gfx_app::ColorFormat;
+
+use particles::MAX_PARTICLES;
+use scene::{Scene, Quad, BACKGROUND};
+use skin::Skin;
+use state::MAIN_WIDTH;
+
+gfx_defines!{
+    vertex Vertex {
+        pos: [f32; 2] = "pos",
+    }
+
+    vertex Instance {
+        center: [f32; 2] = "a_Center",
+        color: [f32; 3] = "a_Color",
+        size: [f32; 2] = "a_Size",
+        uv: [f32; 4] = "a_Uv",
+        style: f32 = "a_Style",
+    }
+
+    pipeline pipe {
+        vbuf: gfx::VertexBuffer<Vertex> = (),
+        instances: gfx::InstanceBuffer<Instance> = (),
+        atlas: gfx::TextureSampler<[f32; 4]> = "t_Atlas",
+        out_color: gfx::RenderTarget<ColorFormat> = "target",
+    }
+}
+
+/// Pixels of text drawn at once, enough for a full menu screen.
+const MAX_TEXT_PIXELS: usize = 8192;
+/// Cells of the rows of a tetris.
+const MAX_UNDER: usize = MAIN_WIDTH * 4;
+const MAX_OVER: usize = MAX_PARTICLES + MAX_TEXT_PIXELS;
+/// Stands in for the atlas when there's none.
+const WHITE_PIXEL: [u8; 4] = [255; 4];
+
+pub struct Renderer<R: gfx::Resources> {
+    bundle: Bundle<R, pipe::Data<R>>,
+    under: Buffer<R, Instance>,
+    over: Buffer<R, Instance>,
+    /// Board instances as last uploaded.
+    board: Vec<Instance>,
+    /// Window size in boxes.
+    width: usize,
+    height: usize,
+    /// Boxes are drawn a whole number of pixels across.
+    is_integer_scale: bool,
+    /// Share of the window the boxes take up across and down, from 0 to 1.
+    extent: [f32; 2],
+}
+
+impl<R: gfx::Resources> Renderer<R> {
+    pub fn new<F: gfx::Factory<R>>(factory: &mut F,
+                                   backend: gfx_app::shade::Backend,
+                                   window_targets: gfx_app::WindowTargets<R>,
+                                   width: usize, height: usize, is_integer_scale: bool,
+                                   skin: &Skin) -> Self {
+        use gfx::traits::FactoryExt;
+
+        let vs = gfx_app::shade::Source {
+            glsl_150: include_bytes!("shader/tetris_150.glslv"),
+            .. gfx_app::shade::Source::empty()
+        };
+        let ps = gfx_app::shade::Source {
+            glsl_150: include_bytes!("shader/tetris_150.glslf"),
+            .. gfx_app::shade::Source::empty()
+        };
+
+        // sized by each instance
+        let vertices = [
+            Vertex { pos: [-1.0, -1.0] },
+            Vertex { pos: [-1.0,  1.0] },
+            Vertex { pos: [ 1.0, -1.0] },
+            Vertex { pos: [ 1.0,  1.0] },
+        ];
+        let indices = [0u16, 1, 2, 1, 2, 3];
+        let (vertex_buffer, slice) = factory.create_vertex_buffer_with_slice(&vertices, &indices as &[u16]);
+
+        let mut instances = |count| factory.create_buffer(count, gfx::buffer::Role::Vertex,
+                                                          gfx::memory::Usage::Dynamic,
+                                                          gfx::memory::Bind::empty()).unwrap();
+        let board = instances(width * height);
+        let under = instances(MAX_UNDER);
+        let over = instances(MAX_OVER);
+
+        let (atlas_width, atlas_height, pixels) = match skin.atlas {
+            Some(ref atlas) => (atlas.width, atlas.height, &atlas.pixels[..]),
+            None => (1, 1, &WHITE_PIXEL[..]),
+        };
+        let kind = texture::Kind::D2(atlas_width as texture::Size, atlas_height as texture::Size, texture::AaMode::Single);
+        let (_, atlas) = factory.create_texture_immutable_u8::<gfx::format::Rgba8>(kind, texture::Mipmap::Provided, &[pixels]).unwrap();
+        // sharp pixels for pixel art
+        let sampler = factory.create_sampler(texture::SamplerInfo::new(texture::FilterMethod::Scale, texture::WrapMode::Clamp));
+
+        let data = pipe::Data {
+            vbuf: vertex_buffer,
+            instances: board,
+            atlas: (atlas, sampler),
+            out_color: window_targets.color,
+        };
+
+        let pso = factory.create_pipeline_simple(
+            vs.select(backend).unwrap(),
+            ps.select(backend).unwrap(),
+            pipe::new()
+        ).unwrap();
+
+        let mut renderer = Renderer {
+            bundle: Bundle::new(slice, pso, data),
+            under: under,
+            over: over,
+            board: Vec::new(),
+            width: width,
+            height: height,
+            is_integer_scale: is_integer_scale,
+            extent: [1.0, 1.0],
+        };
+        renderer.fit();
+        renderer
+    }
+
+    /// Draws to the new targets of a resized window.
+    pub fn resize(&mut self, window_targets: gfx_app::WindowTargets<R>) {
+        self.bundle.data.out_color = window_targets.color;
+        self.fit();
+    }
+
+    /// Works out the share of the target the boxes fill: as much as keeps them square,
+    /// or whole pixels of it with integer scaling. The target is in physical pixels,
+    /// so high DPI screens get more of them per box rather than blurry ones.
+    fn fit(&mut self) {
+        let (target_width, target_height, _, _) = self.bundle.data.out_color.get_dimensions();
+        let (target_width, target_height) = (target_width.max(1) as f32, target_height.max(1) as f32);
+        let mut box_size = (target_width / self.width as f32).min(target_height / self.height as f32);
+        if self.is_integer_scale && box_size >= 1.0 {
+            box_size = box_size.floor();
+        }
+        self.extent = [
+            box_size * self.width as f32 / target_width,
+            box_size * self.height as f32 / target_height,
+        ];
+    }
+
+    pub fn draw<C: gfx::CommandBuffer<R>>(&mut self, encoder: &mut gfx::Encoder<R, C>, scene: &Scene, skin: &Skin) {
+        encoder.clear(&self.bundle.data.out_color, [BACKGROUND[0], BACKGROUND[1], BACKGROUND[2], 1.0]);
+
+        let under: Vec<Instance> = scene.under.iter().take(MAX_UNDER).map(|quad| self.instance(quad, skin)).collect();
+        let buffer = self.under.clone();
+        self.draw_instances(encoder, buffer, &under);
+
+        let board: Vec<Instance> = scene.board.iter().map(|quad| self.instance(quad, skin)).collect();
+        self.update_board(encoder, board);
+        // menus have no board
+        if !self.board.is_empty() {
+            let mut slice = self.bundle.slice.clone();
+            slice.instances = Some((self.board.len() as u32, 0));
+            encoder.draw(&slice, &self.bundle.pso, &self.bundle.data);
+        }
+
+        let over: Vec<Instance> = scene.over.iter().take(MAX_OVER).map(|quad| self.instance(quad, skin)).collect();
+        let buffer = self.over.clone();
+        self.draw_instances(encoder, buffer, &over);
+    }
+
+    /// Uploads the runs of board cells that differ from the last frame's.
+    fn update_board<C: gfx::CommandBuffer<R>>(&mut self, encoder: &mut gfx::Encoder<R, C>, board: Vec<Instance>) {
+        if self.board.len() != board.len() {
+            if !board.is_empty() {
+                encoder.update_buffer(&self.bundle.data.instances, &board, 0).unwrap();
+            }
+            self.board = board;
+            return;
+        }
+        let mut i = 0;
+        while i < board.len() {
+            if is_same(&self.board[i], &board[i]) {
+                i += 1;
+                continue;
+            }
+            let start = i;
+            while i < board.len() && !is_same(&self.board[i], &board[i]) {
+                i += 1;
+            }
+            encoder.update_buffer(&self.bundle.data.instances, &board[start..i], start).unwrap();
+        }
+        self.board = board;
+    }
+
+    fn draw_instances<C: gfx::CommandBuffer<R>>(&self, encoder: &mut gfx::Encoder<R, C>,
+                                                buffer: Buffer<R, Instance>, instances: &[Instance]) {
+        if instances.is_empty() {
+            return;
+        }
+        encoder.update_buffer(&buffer, instances, 0).unwrap();
+        let mut slice = self.bundle.slice.clone();
+        slice.instances = Some((instances.len() as u32, 0));
+        let data = pipe::Data { instances: buffer, .. self.bundle.data.clone() };
+        encoder.draw(&slice, &self.bundle.pso, &data);
+    }
+
+    /// `quad` in normalized device coordinates, centred in the window, filled as `skin` has it.
+    fn instance(&self, quad: &Quad, skin: &Skin) -> Instance {
+        let box_width = 2.0 / self.width as f32 * self.extent[0];
+        let box_height = 2.0 / self.height as f32 * self.extent[1];
+        let (shade, color, uv) = skin.shade(quad);
+        Instance {
+            center: [
+                - self.extent[0] + (quad.center[0] + 0.5) * box_width,
+                  self.extent[1] - (quad.center[1] + 0.5) * box_height,
+            ],
+            color: color,
+            size: [quad.size * box_width / 2.0, quad.size * box_height / 2.0],
+            uv: uv,
+            style: shade as u8 as f32,
+        }
+    }
+}
+
+fn is_same(a: &Instance, b: &Instance) -> bool {
+    a.center == b.center && a.color == b.color && a.size == b.size && a.uv == b.uv && a.style == b.style
+}
@@ -0,0 +1,170 @@
+//! Games kept as fumens, a page per piece as F prints them: saved when they end, watched
+//! from the menu, or turned into an animated GIF or a folder of PNG frames drawn on the CPU
+//! so no window is needed. A page holds a piece where it locked, so a replay shows the
+//! placements one after another, not the moves that led to them.
+
+use std::fs::{self, File};
+use std::io::{self, BufWriter};
+use std::path::{Path, PathBuf};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use image;
+use image::codecs::gif::{GifEncoder, Repeat};
+
+use config::Config;
+use effects::Effects;
+use particles::Particles;
+use raster;
+use scene::Scene;
+use skin::Skin;
+use state::State;
+use state::fumen::{self, Page};
+use state::mode::Mode;
+use state::timer::TICKS_PER_SECOND;
+
+/// Folder finished games are saved to.
+pub const REPLAYS_PATH: &'static str = "replays";
+/// Pages shown a second unless asked otherwise.
+pub const DEFAULT_SPEED: f64 = 2.0;
+
+/// Plays a replay back a page at a time, or a page on or back at the viewer's asking.
+pub struct Viewer {
+    state: State,
+    pages: Vec<Page>,
+    page: usize,
+    ticks: u32,
+    pub is_paused: bool,
+}
+
+impl Viewer {
+    pub fn new(config: &Config, fumen: &str) -> Result<Self, String> {
+        let pages = load(fumen)?;
+        let mut state = replay_state(config);
+        // every page is checked up front, so stepping through them later can't fail
+        for index in (0..pages.len()).rev() {
+            state.show_page(&pages, index).map_err(|err| format!("page {}: {}", index + 1, err))?;
+        }
+        Ok(Viewer {
+            state: state,
+            pages: pages,
+            page: 0,
+            ticks: 0,
+            is_paused: false,
+        })
+    }
+
+    pub fn tick(&mut self) {
+        if self.is_paused {
+            return;
+        }
+        self.ticks += 1;
+        if self.ticks as f64 >= TICKS_PER_SECOND as f64 / DEFAULT_SPEED {
+            self.step(1);
+        }
+    }
+
+    /// Moves `pages` on, or back if negative, staying within the game.
+    pub fn step(&mut self, pages: isize) {
+        let last = self.pages.len() as isize - 1;
+        let page = (self.page as isize + pages).max(0).min(last) as usize;
+        self.ticks = 0;
+        if page != self.page {
+            self.page = page;
+            let _ = self.state.show_page(&self.pages, page);
+        }
+    }
+
+    pub fn scene(&self) -> Scene {
+        Scene::new(&self.state, &Effects::new(false, false), &Particles::new(false))
+    }
+}
+
+/// Keeps the game so far in `REPLAYS_PATH`, named after its mode and the time.
+pub fn save(state: &mut State) -> io::Result<PathBuf> {
+    fs::create_dir_all(REPLAYS_PATH)?;
+    let secs = SystemTime::now().duration_since(UNIX_EPOCH).map(|time| time.as_secs()).unwrap_or(0);
+    let path = Path::new(REPLAYS_PATH).join(format!("{}-{}.txt", state.config.mode.name(), secs));
+    fs::write(&path, state.game_fumen() + "\n")?;
+    Ok(path)
+}
+
+/// Pages of `fumen`, either the fumen itself or a file holding it.
+pub fn load(fumen: &str) -> Result<Vec<Page>, String> {
+    let text = if fumen.trim().starts_with("v115@") {
+        fumen.to_string()
+    }
+    else {
+        fs::read_to_string(fumen).map_err(|err| format!("can't read {}: {}", fumen, err))?
+    };
+    let pages = fumen::decode(&text)?;
+    if pages.is_empty() {
+        return Err("empty fumen".to_string());
+    }
+    Ok(pages)
+}
+
+/// A state to show pages on; zen has no hud and no garbage of its own to get in the way.
+fn replay_state(config: &Config) -> State {
+    let mut config = config.clone();
+    config.mode = Mode::Zen;
+    State::new(&config)
+}
+
+/// Renders the pages of `fumen`, either the fumen itself or a file holding it, `speed` pages
+/// a second with boxes of `box_size` pixels. A `.gif` path gets an animation, any other is
+/// made a folder of numbered PNGs. Frames are written as they're drawn, so only one is held
+/// at a time. Returns how many frames were written.
+pub fn export(config: &Config, fumen: &str, path: &str, speed: f64, box_size: usize) -> Result<usize, String> {
+    let pages = load(fumen)?;
+    let mut state = replay_state(config);
+    let skin = Skin::new(config);
+    // every page is checked first, so a bad one doesn't leave half an export behind
+    for index in (0..pages.len()).rev() {
+        state.show_page(&pages, index).map_err(|err| format!("page {}: {}", index + 1, err))?;
+    }
+
+    let mut output = Output::create(path, (1000.0 / speed).round() as u32)?;
+    for index in 0..pages.len() {
+        let _ = state.show_page(&pages, index);
+        let scene = Scene::new(&state, &Effects::new(false, false), &Particles::new(false));
+        output.write(index, raster::render(&scene, &skin, box_size))?;
+    }
+    Ok(pages.len())
+}
+
+/// Where exported frames go: a GIF, with the delay between frames in milliseconds, or a
+/// folder of PNGs.
+enum Output {
+    Gif(GifEncoder<BufWriter<File>>, u32),
+    Frames(PathBuf),
+}
+
+impl Output {
+    fn create(path: &str, delay_millis: u32) -> Result<Self, String> {
+        if path.to_lowercase().ends_with(".gif") {
+            let file = File::create(path).map_err(|err| format!("can't create {}: {}", path, err))?;
+            let mut encoder = GifEncoder::new(BufWriter::new(file));
+            encoder.set_repeat(Repeat::Infinite).map_err(|err| err.to_string())?;
+            Ok(Output::Gif(encoder, delay_millis))
+        }
+        else {
+            fs::create_dir_all(path).map_err(|err| format!("can't create {}: {}", path, err))?;
+            Ok(Output::Frames(PathBuf::from(path)))
+        }
+    }
+
+    fn write(&mut self, index: usize, frame: raster::Image) -> Result<(), String> {
+        match *self {
+            Output::Gif(ref mut encoder, delay_millis) => {
+                let buffer = image::RgbaImage::from_raw(frame.width as u32, frame.height as u32, frame.pixels)
+                    .ok_or("frame of the wrong size")?;
+                let delay = image::Delay::from_numer_denom_ms(delay_millis, 1);
+                encoder.encode_frame(image::Frame::from_parts(buffer, 0, 0, delay)).map_err(|err| err.to_string())
+            },
+            Output::Frames(ref folder) => {
+                let file = folder.join(format!("frame-{:04}.png", index + 1));
+                frame.save_png(&file.to_string_lossy())
+            },
+        }
+    }
+}
@@ -0,0 +1,269 @@
+//! What a frame shows, laid out as squares in boxes of the window and kept apart from gfx,
+//! so the renderer only uploads and draws them.
+
+use state::{State, PREVIEW_WIDTH, HOLD_WIDTH, MAIN_WIDTH, VISIBLE_HEIGHT, VANISH_ROWS};
+use state::color::{self, Color};
+use effects::{self, Effects};
+use particles::Particles;
+use font;
+
+/// Colour behind everything.
+pub const BACKGROUND: [f32; 3] = [0.1, 0.1, 0.1];
+/// Ticks cleared rows spend lit and unlit while they wait to collapse.
+const CLEAR_FLASH_TICKS: u32 = 4;
+/// Row of the visible board the callouts start on.
+const CALLOUT_ROW: usize = 4;
+/// Largest callout pixel, in boxes, so short lines don't fill the board.
+const CALLOUT_MAX_SCALE: f32 = 0.4;
+/// Size of a menu pixel, in boxes, and the largest a title's can be.
+const MENU_SCALE: f32 = 0.25;
+const MENU_TITLE_SCALE: f32 = 0.6;
+/// Rows above a menu's title and between it and the first line.
+const MENU_TOP: f32 = 1.0;
+const MENU_GAP: f32 = 1.5;
+
+/// A square centred on `center`, a column and row of the window, `size` boxes across.
+#[derive(Clone, Copy, Debug)]
+pub struct Quad {
+    pub center: [f32; 2],
+    pub color: [f32; 3],
+    pub size: f32,
+    pub fill: Fill,
+}
+
+/// What a quad is, for the skin to draw it by.
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum Fill {
+    /// Drawn in its colour whatever the skin: panels, text and particles.
+    Plain,
+    /// Part of a piece or the stack.
+    Block,
+    /// An empty cell of the main board.
+    Empty,
+}
+
+pub struct Scene {
+    /// Size of the window in boxes.
+    pub width: usize,
+    pub height: usize,
+    /// One square per cell of hold, main board and preview, always in that order,
+    /// so that frames compare cell by cell. Hidden cells have no size.
+    pub board: Vec<Quad>,
+    /// Drawn before the board: cleared rows shrinking away.
+    pub under: Vec<Quad>,
+    /// Drawn over the board: particles and text.
+    pub over: Vec<Quad>,
+}
+
+impl Scene {
+    pub fn new(state: &State, effects: &Effects, particles: &Particles) -> Self {
+        let mut scene = Scene {
+            width: state.dim().w,
+            height: state.dim().h,
+            board: Vec::with_capacity(state.dim().w * state.dim().h),
+            under: Vec::new(),
+            over: Vec::new(),
+        };
+        for pos in state.hold.get_iter() {
+            scene.board.push(Quad::cell(pos.x as f32, pos.y as f32, state.hold.tile(pos)));
+        }
+
+        // cleared rows flash until they collapse
+        let flashing = match state.clearing_rows() {
+            Some((rows, ticks)) if effects.is_animated() && ticks / CLEAR_FLASH_TICKS % 2 == 0 => rows,
+            _ => Vec::new(),
+        };
+        let dip = effects.dip();
+        // only the stack settles and dips, the falling piece stays where it is
+        let piece = if state.is_between_pieces() {
+            Vec::new()
+        }
+        else {
+            state.piece.try_into(&state.main).unwrap_or_default()
+        };
+        // the vanish zone is off the window, pieces show up as they drop out of it
+        for pos in state.main.get_iter().filter(|pos| pos.y >= VANISH_ROWS) {
+            let mut row = (pos.y - VANISH_ROWS) as f32;
+            if !piece.contains(&pos) {
+                row += dip - effects.lift(pos.y);
+            }
+            let color = if flashing.contains(&pos.y) { color::WHITE } else { *state.main.tile(pos) };
+            let mut quad = Quad::cell((pos.x + HOLD_WIDTH) as f32, row, &color);
+            if row < 0.0 {
+                quad.size = 0.0;
+            }
+            scene.board.push(quad);
+        }
+        let offset = state.dim().w - PREVIEW_WIDTH;
+        for pos in state.preview.get_iter() {
+            scene.board.push(Quad::cell((pos.x + offset) as f32, pos.y as f32, state.preview.tile(pos)));
+        }
+
+        // collapsed rows shrink away, under the stack coming down over them
+        if let Some((rows, faded)) = effects.fading_rows() {
+            let color = effects::mix(color::WHITE.into(), Color::default().into(), faded);
+            for &row in rows.iter().filter(|&&row| row >= VANISH_ROWS) {
+                for column in 0..MAIN_WIDTH {
+                    scene.under.push(Quad {
+                        center: [(column + HOLD_WIDTH) as f32, (row - VANISH_ROWS) as f32 + dip],
+                        color: color,
+                        size: 1.0 - faded,
+                        fill: Fill::Block,
+                    });
+                }
+            }
+        }
+
+        for particle in particles.iter().filter(|particle| particle.pos[1] >= VANISH_ROWS as f32) {
+            scene.over.push(Quad {
+                center: [particle.pos[0] + HOLD_WIDTH as f32, particle.pos[1] - VANISH_ROWS as f32],
+                color: particle.color,
+                size: particle.size(),
+                fill: Fill::Plain,
+            });
+        }
+        if let Some(text) = state.hud() {
+            let width = font::text_width(&text) as f32;
+            let scale = (PREVIEW_WIDTH as f32 / (width + 2.0)).min(1.0 / font::HEIGHT as f32);
+            let left = offset as f32 + (PREVIEW_WIDTH as f32 - width * scale) / 2.0 - 0.5;
+            let top = (1.0 - font::HEIGHT as f32 * scale) / 2.0 - 0.5;
+            scene.text(&text, left, top, scale, color::WHITE.into());
+        }
+        if let Some((lines, brightness)) = effects.callouts() {
+            let color = effects::mix(Color::default().into(), color::WHITE.into(), brightness);
+            scene.board_text(lines, color);
+        }
+        scene
+    }
+
+    /// Puts how a game went over the board, on a backdrop so the stack doesn't get in the way.
+    pub fn results(&mut self, lines: &[String]) {
+        for row in 0..self.height {
+            for column in 0..MAIN_WIDTH {
+                self.over.push(Quad {
+                    center: [(column + HOLD_WIDTH) as f32, row as f32],
+                    color: BACKGROUND,
+                    size: 1.0,
+                    fill: Fill::Plain,
+                });
+            }
+        }
+        self.board_text(lines, color::WHITE.into());
+    }
+
+    /// Lines centred across the main board from `CALLOUT_ROW` down, in one size, the widest
+    /// filling the board but for a box each side.
+    fn board_text(&mut self, lines: &[String], color: [f32; 3]) {
+        let width = lines.iter().map(|line| font::text_width(line)).max().unwrap_or(0) as f32;
+        let scale = ((MAIN_WIDTH as f32 - 2.0) / width.max(1.0)).min(CALLOUT_MAX_SCALE);
+        let mut top = CALLOUT_ROW as f32 - 0.5;
+        for line in lines {
+            let width = font::text_width(line) as f32;
+            let left = HOLD_WIDTH as f32 + (MAIN_WIDTH as f32 - width * scale) / 2.0 - 0.5;
+            self.text(line, left, top, scale, color);
+            top += (font::HEIGHT + 1) as f32 * scale;
+        }
+    }
+
+    /// A screen of text: `title` and under it `lines` of a label on the left and a value on
+    /// the right, `selected` lit, scrolled so it's in view.
+    pub fn menu(title: &str, lines: &[(String, String)], selected: Option<usize>) -> Self {
+        let width = HOLD_WIDTH + MAIN_WIDTH + PREVIEW_WIDTH;
+        let mut scene = Scene {
+            width: width,
+            height: VISIBLE_HEIGHT,
+            board: Vec::new(),
+            under: Vec::new(),
+            over: Vec::new(),
+        };
+        let title_width = font::text_width(title) as f32;
+        let scale = ((width as f32 - 2.0) / title_width.max(1.0)).min(MENU_TITLE_SCALE);
+        let left = (width as f32 - title_width * scale) / 2.0 - 0.5;
+        scene.text(title, left, MENU_TOP - 0.5, scale, color::CYAN.into());
+
+        let pitch = (font::HEIGHT + 1) as f32 * MENU_SCALE;
+        let top = MENU_TOP + font::HEIGHT as f32 * scale + MENU_GAP;
+        let shown = ((VISIBLE_HEIGHT as f32 - top) / pitch) as usize;
+        let first = match selected {
+            Some(selected) if selected >= shown => selected + 1 - shown,
+            _ => 0,
+        };
+        for (i, &(ref label, ref value)) in lines.iter().enumerate().skip(first).take(shown) {
+            let color = if selected == Some(i) { color::WHITE } else { color::PREVIEW };
+            let row = top + (i - first) as f32 * pitch - 0.5;
+            scene.text(label, 0.5, row, MENU_SCALE, color.into());
+            let right = width as f32 - 1.5 - font::text_width(value) as f32 * MENU_SCALE;
+            scene.text(value, right, row, MENU_SCALE, color.into());
+        }
+        scene
+    }
+
+    /// Pixels of `text`, `scale` boxes each, from `left` and `top` in boxes of the window.
+    fn text(&mut self, text: &str, left: f32, top: f32, scale: f32, color: [f32; 3]) {
+        for (px, py) in font::pixels(text) {
+            self.over.push(Quad {
+                center: [left + (px as f32 + 0.5) * scale, top + (py as f32 + 0.5) * scale],
+                color: color,
+                size: scale,
+                fill: Fill::Plain,
+            });
+        }
+    }
+}
+
+impl Quad {
+    fn cell(x: f32, y: f32, color: &Color) -> Self {
+        let fill = if *color == color::PREVIEW {
+            Fill::Plain
+        }
+        else if *color == Color::default() {
+            Fill::Empty
+        }
+        else {
+            Fill::Block
+        };
+        Quad {
+            center: [x, y],
+            color: color.into(),
+            size: 1.0,
+            fill: fill,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use config::Config;
+    use state::{State, HEIGHT, HOLD_WIDTH, VANISH_ROWS};
+    use state::color::Color;
+    use state::event::Event;
+    use effects::Effects;
+    use particles::Particles;
+    use super::Scene;
+
+    fn has_quad_at(scene: &Scene, x: usize, y: usize) -> bool {
+        let center = [(x + HOLD_WIDTH) as f32, (y - VANISH_ROWS) as f32];
+        scene.board.iter().any(|quad| quad.center == center && quad.size > 0.0)
+    }
+
+    #[test]
+    fn falling_piece_stays_put_while_the_stack_settles() {
+        let mut state = State::new(&Config::default());
+        state.load_fumen("v115@9gF8DeF8DeF8DeF8Ne1RJ").unwrap();
+        let mut effects = Effects::new(true, false);
+        effects.on(&Event::LinesCleared { rows: vec![HEIGHT - 1], cells: Vec::new(), spin: false, combo: 0, b2b: false });
+        effects.on(&Event::Dropped(10));
+        let scene = Scene::new(&state, &effects, &Particles::new(false));
+        let piece = state.piece.try_into(&state.main).unwrap();
+        for pos in piece.iter() {
+            assert!(has_quad_at(&scene, pos.x, pos.y));
+        }
+        let stack: Vec<_> = state.main.get_iter()
+            .filter(|pos| !piece.contains(pos) && *state.main.tile(*pos) != Color::default())
+            .collect();
+        assert_eq!(stack.len(), 24);
+        for pos in stack {
+            assert!(!has_quad_at(&scene, pos.x, pos.y));
+        }
+    }
+}
@@ -0,0 +1,159 @@
+//! How board cells are drawn: flat, with a bevel or outline worked out in the shader,
+//! or as sprites out of a texture atlas, with optional grid lines over the empty board.
+
+use std::str::FromStr;
+
+use image;
+
+use config::Config;
+use scene::{Quad, Fill};
+use state::color::{self, Color};
+
+/// Colours with a sprite of their own, in atlas order. Pieces go by colour, so under
+/// rulesets that give each kind one colour every kind gets its own sprite.
+const SPRITE_COLORS: [Color; 9] = [
+    color::RED, color::GREEN, color::BLUE, color::YELLOW, color::MAGENTA,
+    color::CYAN, color::ORANGE, color::WHITE, color::GARBAGE,
+];
+/// Sprites in an atlas: one per colour above and a last one tinted with any other colour.
+pub const SPRITES: usize = 10;
+const NO_UV: [f32; 4] = [0.0; 4];
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum Style {
+    Flat,
+    Bevel,
+    Outline,
+    Atlas,
+}
+
+impl FromStr for Style {
+    type Err = ();
+
+    fn from_str(s: &str) -> Result<Self, ()> {
+        match s {
+            "flat" => Ok(Style::Flat),
+            "bevel" => Ok(Style::Bevel),
+            "outline" => Ok(Style::Outline),
+            "atlas" => Ok(Style::Atlas),
+            _ => Err(()),
+        }
+    }
+}
+
+impl Style {
+    /// Name in the config, as `from_str` takes it.
+    pub fn name(&self) -> &'static str {
+        match *self {
+            Style::Flat => "flat",
+            Style::Bevel => "bevel",
+            Style::Outline => "outline",
+            Style::Atlas => "atlas",
+        }
+    }
+}
+
+/// How a quad is filled in, numbered as in the fragment shader.
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum Shade {
+    Flat = 0,
+    Bevel = 1,
+    Outline = 2,
+    Atlas = 3,
+    Grid = 4,
+}
+
+/// Sprites side by side in a strip, square and as tall as the image.
+pub struct Atlas {
+    pub width: u32,
+    pub height: u32,
+    /// RGBA, a row at a time from the top.
+    pub pixels: Vec<u8>,
+}
+
+impl Atlas {
+    pub fn load(path: &str) -> Result<Atlas, String> {
+        let image = image::open(path).map_err(|err| err.to_string())?.to_rgba8();
+        let (width, height) = image.dimensions();
+        if height == 0 || width < height * SPRITES as u32 {
+            return Err(format!("expected {} square sprites side by side, got {}x{}", SPRITES, width, height));
+        }
+        Ok(Atlas {
+            width: width,
+            height: height,
+            pixels: image.into_raw(),
+        })
+    }
+
+    /// Left, top, width and height of `sprite` as fractions of the atlas.
+    pub fn uv(&self, sprite: usize) -> [f32; 4] {
+        let width = self.height as f32 / self.width as f32;
+        [sprite as f32 * width, 0.0, width, 1.0]
+    }
+}
+
+pub struct Skin {
+    pub style: Style,
+    pub has_grid: bool,
+    pub atlas: Option<Atlas>,
+}
+
+impl Skin {
+    /// The skin the config asks for, or a flat one if its atlas can't be loaded.
+    pub fn new(config: &Config) -> Self {
+        let mut style = config.skin;
+        let atlas = match Atlas::load(&config.skin_atlas) {
+            Ok(atlas) => Some(atlas),
+            Err(err) => {
+                if style == Style::Atlas {
+                    eprintln!("Can't load skin atlas {}: {}", config.skin_atlas, err);
+                    style = Style::Flat;
+                }
+                None
+            },
+        };
+        Skin {
+            style: style,
+            has_grid: config.grid,
+            atlas: atlas,
+        }
+    }
+
+    /// Moves on to the next style, skipping the atlas if there's none.
+    pub fn next_style(&mut self) {
+        self.style = match self.style {
+            Style::Flat => Style::Bevel,
+            Style::Bevel => Style::Outline,
+            Style::Outline if self.atlas.is_some() => Style::Atlas,
+            _ => Style::Flat,
+        };
+    }
+
+    pub fn toggle_grid(&mut self) {
+        self.has_grid = !self.has_grid;
+    }
+
+    /// How `quad` is filled in: the shading, the colour it shades and the part of the atlas
+    /// it takes its pixels from.
+    pub fn shade(&self, quad: &Quad) -> (Shade, [f32; 3], [f32; 4]) {
+        match (quad.fill, self.style, &self.atlas) {
+            (Fill::Empty, _, _) if self.has_grid => (Shade::Grid, quad.color, NO_UV),
+            (Fill::Block, Style::Bevel, _) => (Shade::Bevel, quad.color, NO_UV),
+            (Fill::Block, Style::Outline, _) => (Shade::Outline, quad.color, NO_UV),
+            (Fill::Block, Style::Atlas, &Some(ref atlas)) => {
+                let (sprite, is_tinted) = sprite(quad.color);
+                let color = if is_tinted { quad.color } else { [1.0; 3] };
+                (Shade::Atlas, color, atlas.uv(sprite))
+            },
+            _ => (Shade::Flat, quad.color, NO_UV),
+        }
+    }
+}
+
+/// Sprite drawn for a cell of `color`, and whether it's tinted with the colour.
+fn sprite(color: [f32; 3]) -> (usize, bool) {
+    match SPRITE_COLORS.iter().position(|&sprite| Into::<[f32; 3]>::into(sprite) == color) {
+        Some(sprite) => (sprite, false),
+        None => (SPRITES - 1, true),
+    }
+}
@@ -9,9 +9,11 @@ pub const BLUE:    Color = Color([0.0f32, 0.0f32, 0.8f32]);
 pub const YELLOW:  Color = Color([0.8f32, 0.8f32, 0.0f32]);
 pub const MAGENTA: Color = Color([0.8f32, 0.0f32, 0.8f32]);
 pub const CYAN:    Color = Color([0.0f32, 0.8f32, 0.8f32]);
+pub const ORANGE:  Color = Color([0.8f32, 0.4f32, 0.0f32]);
 pub const WHITE:   Color = Color([0.8f32, 0.8f32, 0.8f32]);
 pub const GRAY:    Color = Color([0.1f32, 0.1f32, 0.1f32]);
 pub const PREVIEW: Color = Color([0.4f32, 0.4f32, 0.4f32]);
+pub const GARBAGE: Color = Color([0.3f32, 0.3f32, 0.3f32]);
 
 impl Generate for Color {
     fn generate() -> Self {
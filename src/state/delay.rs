@@ -0,0 +1,125 @@
+use super::{State, Visible};
+use super::mode::Mode;
+
+/// Where the game is between one piece and the next.
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum Phase {
+    /// A piece is in play.
+    Falling,
+    /// Full rows stay on the board for `ticks` before they collapse, then the next piece
+    /// waits `entry` ticks.
+    Clearing { ticks: u32, entry: u32, is_tspin: bool },
+    /// The next piece comes in after `ticks`.
+    Entry { ticks: u32 },
+}
+
+/// Turns and hold asked for while no piece is in play, done as the next one comes in.
+#[derive(Clone, Copy, Default, Debug)]
+pub struct Initial {
+    turns: usize,
+    is_hold: bool,
+}
+
+impl State {
+    /// Whether the last piece has locked and the next one is yet to come in.
+    pub fn is_between_pieces(&self) -> bool {
+        self.phase != Phase::Falling
+    }
+
+    /// Rows waiting to collapse and the ticks they have left on the board.
+    pub fn clearing_rows(&self) -> Option<(Vec<usize>, u32)> {
+        match self.phase {
+            Phase::Clearing { ticks, .. } => Some((self.filled_rows(), ticks)),
+            _ => None,
+        }
+    }
+
+    /// Ticks between a lock and the next piece, the config's if it sets one.
+    /// Zen has no delays, its undo works a piece at a time.
+    pub fn entry_delay(&self, height: usize) -> u32 {
+        if self.config.mode == Mode::Zen {
+            return 0;
+        }
+        self.config.are.unwrap_or_else(|| self.ruleset.entry_delay(height))
+    }
+
+    /// Ticks full rows stay on the board before they collapse.
+    pub fn line_clear_delay(&self) -> u32 {
+        if self.config.mode == Mode::Zen {
+            return 0;
+        }
+        self.config.line_clear_delay.unwrap_or(self.ruleset.line_clear_delay)
+    }
+
+    /// Queues a turn of `turns` quarters clockwise for the next piece (IRS).
+    pub fn turn_on_entry(&mut self, turns: usize) {
+        if self.ruleset.has_initial_actions && self.is_between_pieces() {
+            self.initial.turns = (self.initial.turns + turns) % 4;
+        }
+    }
+
+    /// Holds the next piece as it comes in (IHS).
+    pub fn hold_on_entry(&mut self) {
+        if self.ruleset.has_initial_actions && self.is_between_pieces() {
+            self.initial.is_hold = true;
+        }
+    }
+
+    /// Counts down the clearing and entry phases, collapsing rows and bringing in
+    /// the next piece when they run out.
+    pub fn tick_delay(&mut self) {
+        match self.phase {
+            Phase::Falling => (),
+            Phase::Clearing { ticks, entry, is_tspin } => {
+                if ticks > 1 {
+                    self.phase = Phase::Clearing { ticks: ticks - 1, entry: entry, is_tspin: is_tspin };
+                }
+                else {
+                    self.phase = Phase::Falling;
+                    self.clear_rows(is_tspin, entry);
+                }
+            },
+            Phase::Entry { ticks } => {
+                if ticks > 1 {
+                    self.phase = Phase::Entry { ticks: ticks - 1 };
+                }
+                else {
+                    self.phase = Phase::Falling;
+                    self.next_piece();
+                }
+            },
+        }
+    }
+
+    /// Waits `entry` ticks for the next piece, or brings it straight in.
+    pub fn enter_after(&mut self, entry: u32) {
+        if entry > 0 {
+            self.phase = Phase::Entry { ticks: entry };
+        }
+        else {
+            self.next_piece();
+        }
+    }
+
+    /// Spawns the next piece, doing the hold and turn asked for while waiting for it.
+    /// A turn that doesn't fit is dropped and the piece comes in as it spawns.
+    fn next_piece(&mut self) {
+        let initial = self.initial;
+        self.initial = Initial::default();
+        if self.spawn_piece().is_err() {
+            return;
+        }
+        if initial.is_hold {
+            self.hold_piece();
+            if self.is_gameover {
+                return;
+            }
+        }
+        if initial.turns > 0 {
+            self.draw_piece(Visible::No);
+            let _ = self.turn_piece(initial.turns);
+        }
+        self.draw_piece(Visible::Yes);
+        self.record_snapshot();
+    }
+}
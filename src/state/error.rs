@@ -0,0 +1,33 @@
+use ::std::fmt;
+
+use super::map::Pos;
+
+/// Why a piece couldn't go where it was sent, or why the stack can't take any more.
+#[derive(Clone, PartialEq, Debug)]
+pub enum Error {
+    /// Part of the piece would be off the board.
+    OutOfBounds,
+    /// The piece would overlap the stack at `cells`.
+    Collision { cells: Vec<Pos> },
+    /// A new piece overlaps the stack where it comes in.
+    BlockOut,
+    /// A piece locked entirely above the skyline.
+    LockOut,
+    /// Garbage pushed the stack through the ceiling.
+    TopOut,
+    /// A fixed queue, as in puzzles, has run out.
+    NoPieces,
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Error::OutOfBounds => write!(f, "out of bounds"),
+            Error::Collision { ref cells } => write!(f, "collides with the stack at {} cells", cells.len()),
+            Error::BlockOut => write!(f, "block out"),
+            Error::LockOut => write!(f, "lock out"),
+            Error::TopOut => write!(f, "top out"),
+            Error::NoPieces => write!(f, "no pieces left"),
+        }
+    }
+}
@@ -0,0 +1,101 @@
+use ::std::fmt;
+use ::std::mem;
+
+use super::State;
+use super::color::Color;
+use super::template::{DeltaPos, Kind};
+
+/// Something that happened in the game, in the order it happened.
+/// The game itself reads only a few of these, the rest is there for subscribers.
+#[allow(dead_code)]
+#[derive(Clone, Debug)]
+pub enum Event {
+    PieceSpawned(Kind),
+    Moved(DeltaPos),
+    /// A hard or sonic drop, and how many rows it took the piece down.
+    Dropped(usize),
+    /// `kick` is the index of the kick test that let the piece turn, 0 when it turned in place.
+    Rotated { kick: usize },
+    Locked(Kind),
+    /// `rows` are where the cleared rows were, counting down the board, before the stack
+    /// collapsed. `combo` counts the clearing locks right before this one, `b2b` is set for
+    /// a tetris or spin clear following another without an easier clear in between.
+    /// `cells` are the colours the cleared rows had, a row after another.
+    LinesCleared { rows: Vec<usize>, cells: Vec<Color>, spin: bool, combo: usize, b2b: bool },
+    PerfectClear,
+    LevelUp(u32),
+    Hold,
+    GameOver { reason: Reason },
+}
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum Reason {
+    /// The mode's goal was reached.
+    Finished,
+    TimeUp,
+    /// A new piece had no room to come in.
+    BlockOut,
+    /// A piece locked entirely above the skyline.
+    LockOut,
+    /// Garbage pushed the stack or the falling piece through the ceiling.
+    TopOut,
+}
+
+impl Reason {
+    pub fn is_complete(&self) -> bool {
+        *self == Reason::Finished || *self == Reason::TimeUp
+    }
+}
+
+impl fmt::Display for Reason {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Reason::Finished => write!(f, "finished"),
+            Reason::TimeUp => write!(f, "time up"),
+            Reason::BlockOut => write!(f, "block out"),
+            Reason::LockOut => write!(f, "lock out"),
+            Reason::TopOut => write!(f, "top out"),
+        }
+    }
+}
+
+/// Subscribers and the events not yet taken by the frontend.
+pub struct Events {
+    pending: Vec<Event>,
+    observers: Vec<Box<dyn FnMut(&Event)>>,
+}
+
+impl Events {
+    pub fn new() -> Self {
+        Events {
+            pending: Vec::new(),
+            observers: Vec::new(),
+        }
+    }
+}
+
+impl State {
+    /// Calls `observer` with every event from now on.
+    pub fn subscribe<F: FnMut(&Event) + 'static>(&mut self, observer: F) {
+        self.events.observers.push(Box::new(observer));
+    }
+
+    /// Takes the events since the last call, for a frontend polling once a frame.
+    pub fn take_events(&mut self) -> Vec<Event> {
+        mem::replace(&mut self.events.pending, Vec::new())
+    }
+
+    pub fn emit(&mut self, event: Event) {
+        for observer in self.events.observers.iter_mut() {
+            observer(&event);
+        }
+        self.events.pending.push(event);
+    }
+
+    pub fn game_over(&mut self, reason: Reason) {
+        self.is_gameover = true;
+        self.is_complete = reason.is_complete();
+        self.stopwatch.stop();
+        self.emit(Event::GameOver { reason: reason });
+    }
+}
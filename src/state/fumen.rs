@@ -0,0 +1,787 @@
+//! Fumen (v115) strings, the usual way of sharing boards: a list of pages, each a field,
+//! an optional piece and a comment, packed into base64-like text.
+//!
+//! The fumen field is 23 rows tall plus a garbage row under the floor; it lines up with the
+//! bottom 23 rows of our board, leaving out the top row of the vanish zone. Board cells keep
+//! the kind of piece they came from, so they go out as that kind whatever their colour, and
+//! garbage goes out grey.
+
+use super::{State, Visible, Piece, Pos, Color, Map, HEIGHT, draw_in_panel};
+use super::color;
+use super::template::{Kind, DeltaPos};
+use super::ruleset::Ruleset;
+
+const PREFIX: &'static str = "v115@";
+const TABLE: &'static [u8] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+const COMMENT_TABLE: &'static [u8] =
+    b" !\"#$%&'()*+,-./0123456789:;<=>?@ABCDEFGHIJKLMNOPQRSTUVWXYZ[\\]^_`abcdefghijklmnopqrstuvwxyz{|}~";
+const COMMENT_BASE: u32 = 96;
+/// Longest escaped comment that fits the two digit length.
+const MAX_COMMENT: usize = 64 * 64 - 1;
+const WIDTH: usize = 10;
+/// Rows above the garbage row.
+const TOP: usize = 23;
+const BLOCKS: usize = (TOP + 1) * WIDTH;
+/// Field diff meaning "same as the previous page".
+const UNCHANGED: u32 = 8 * BLOCKS as u32 + BLOCKS as u32 - 1;
+
+/// Colours of imported cells and pieces, whatever the ruleset's own.
+const KIND_COLORS: [(Kind, Color); 7] = [
+    (Kind::I, color::CYAN),
+    (Kind::O, color::YELLOW),
+    (Kind::T, color::MAGENTA),
+    (Kind::S, color::GREEN),
+    (Kind::Z, color::RED),
+    (Kind::J, color::BLUE),
+    (Kind::L, color::ORANGE),
+];
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum Cell {
+    Empty,
+    Block(Kind),
+    Gray,
+}
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum Rotation {
+    Spawn,
+    Right,
+    Reverse,
+    Left,
+}
+
+/// A piece as fumen places it: SRS centre, `y` counted up from the bottom row.
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub struct Operation {
+    pub kind: Kind,
+    pub rotation: Rotation,
+    pub x: isize,
+    pub y: isize,
+}
+
+#[derive(Clone, Debug)]
+pub struct Page {
+    /// `BLOCKS` cells, top row first, the garbage row last.
+    pub field: Vec<Cell>,
+    pub operation: Option<Operation>,
+    pub comment: String,
+    /// Place the piece and clear lines before the next page.
+    pub lock: bool,
+    /// Push the garbage row up into the field before the next page.
+    pub rise: bool,
+    /// Flip the field before the next page.
+    pub mirror: bool,
+}
+
+impl Page {
+    pub fn new(field: Vec<Cell>, operation: Option<Operation>) -> Self {
+        Page {
+            field: field,
+            operation: operation,
+            comment: String::new(),
+            lock: true,
+            rise: false,
+            mirror: false,
+        }
+    }
+
+    /// The field the following page starts from.
+    fn next_field(&self) -> Vec<Cell> {
+        let mut field = self.field.clone();
+        if !self.lock {
+            return field;
+        }
+        if let Some(operation) = self.operation {
+            for (x, y) in operation.cells() {
+                if 0 <= x && x < WIDTH as isize && 0 <= y && y < TOP as isize {
+                    field[index(x as usize, y as usize)] = Cell::Block(operation.kind);
+                }
+            }
+        }
+        let mut rows: Vec<Vec<Cell>> = field[..TOP * WIDTH].chunks(WIDTH)
+            .filter(|row| row.iter().any(|&cell| cell == Cell::Empty))
+            .map(|row| row.to_vec())
+            .collect();
+        while rows.len() < TOP {
+            rows.insert(0, vec![Cell::Empty; WIDTH]);
+        }
+        let mut garbage = field[TOP * WIDTH..].to_vec();
+        if self.rise {
+            rows.remove(0);
+            rows.push(garbage.clone());
+            garbage = vec![Cell::Empty; WIDTH];
+        }
+        if self.mirror {
+            for row in rows.iter_mut() {
+                row.reverse();
+            }
+        }
+        rows.into_iter().flat_map(|row| row.into_iter()).chain(garbage.into_iter()).collect()
+    }
+}
+
+impl Operation {
+    /// Cells as `(x, y)`, `y` going up.
+    pub fn cells(&self) -> Vec<(isize, isize)> {
+        blocks(self.kind, self.rotation).iter()
+            .map(|&(dx, dy)| (self.x + dx, self.y + dy))
+            .collect()
+    }
+
+    fn location(&self) -> u32 {
+        let (mut x, mut y) = (self.x, self.y);
+        match (self.kind, self.rotation) {
+            (Kind::O, Rotation::Left) => { x -= 1; y += 1; },
+            (Kind::O, Rotation::Reverse) => x -= 1,
+            (Kind::O, Rotation::Spawn) => y += 1,
+            (Kind::I, Rotation::Reverse) => x -= 1,
+            (Kind::I, Rotation::Left) => y += 1,
+            (Kind::S, Rotation::Spawn) => y += 1,
+            (Kind::S, Rotation::Right) => x -= 1,
+            (Kind::Z, Rotation::Spawn) => y += 1,
+            (Kind::Z, Rotation::Left) => x += 1,
+            _ => (),
+        }
+        ((TOP as isize - y - 1) * WIDTH as isize + x) as u32
+    }
+
+    fn from_location(kind: Kind, rotation: Rotation, location: u32) -> Self {
+        let mut x = (location % WIDTH as u32) as isize;
+        let mut y = TOP as isize - (location / WIDTH as u32) as isize - 1;
+        match (kind, rotation) {
+            (Kind::O, Rotation::Left) => { x += 1; y -= 1; },
+            (Kind::O, Rotation::Reverse) => x += 1,
+            (Kind::O, Rotation::Spawn) => y -= 1,
+            (Kind::I, Rotation::Reverse) => x += 1,
+            (Kind::I, Rotation::Left) => y -= 1,
+            (Kind::S, Rotation::Spawn) => y -= 1,
+            (Kind::S, Rotation::Right) => x += 1,
+            (Kind::Z, Rotation::Spawn) => y -= 1,
+            (Kind::Z, Rotation::Left) => x -= 1,
+            _ => (),
+        }
+        Operation { kind: kind, rotation: rotation, x: x, y: y }
+    }
+}
+
+/// SRS cell offsets, `y` going up.
+fn blocks(kind: Kind, rotation: Rotation) -> [(isize, isize); 4] {
+    let spawn = match kind {
+        Kind::I => [(0, 0), (-1, 0), (1, 0), (2, 0)],
+        Kind::T => [(0, 0), (-1, 0), (1, 0), (0, 1)],
+        Kind::O => [(0, 0), (1, 0), (0, 1), (1, 1)],
+        Kind::L => [(0, 0), (-1, 0), (1, 0), (1, 1)],
+        Kind::J => [(0, 0), (-1, 0), (1, 0), (-1, 1)],
+        Kind::S => [(0, 0), (-1, 0), (0, 1), (1, 1)],
+        Kind::Z => [(0, 0), (1, 0), (0, 1), (-1, 1)],
+    };
+    let mut result = spawn;
+    for (output, &(x, y)) in result.iter_mut().zip(spawn.iter()) {
+        *output = match rotation {
+            Rotation::Spawn => (x, y),
+            Rotation::Right => (y, -x),
+            Rotation::Reverse => (-x, -y),
+            Rotation::Left => (-y, x),
+        };
+    }
+    result
+}
+
+fn index(x: usize, y: usize) -> usize {
+    (TOP - y - 1) * WIDTH + x
+}
+
+fn cell_code(cell: Cell) -> u32 {
+    match cell {
+        Cell::Empty => 0,
+        Cell::Block(kind) => kind_code(kind),
+        Cell::Gray => 8,
+    }
+}
+
+fn kind_code(kind: Kind) -> u32 {
+    match kind {
+        Kind::I => 1,
+        Kind::L => 2,
+        Kind::O => 3,
+        Kind::Z => 4,
+        Kind::T => 5,
+        Kind::J => 6,
+        Kind::S => 7,
+    }
+}
+
+fn code_cell(code: u32) -> Result<Cell, String> {
+    Ok(match code {
+        0 => Cell::Empty,
+        1 => Cell::Block(Kind::I),
+        2 => Cell::Block(Kind::L),
+        3 => Cell::Block(Kind::O),
+        4 => Cell::Block(Kind::Z),
+        5 => Cell::Block(Kind::T),
+        6 => Cell::Block(Kind::J),
+        7 => Cell::Block(Kind::S),
+        8 => Cell::Gray,
+        _ => return Err(format!("bad cell {}", code)),
+    })
+}
+
+fn rotation_code(rotation: Rotation) -> u32 {
+    match rotation {
+        Rotation::Reverse => 0,
+        Rotation::Right => 1,
+        Rotation::Spawn => 2,
+        Rotation::Left => 3,
+    }
+}
+
+const ROTATIONS: [Rotation; 4] = [Rotation::Reverse, Rotation::Right, Rotation::Spawn, Rotation::Left];
+
+struct Reader {
+    values: Vec<u32>,
+    cursor: usize,
+}
+
+impl Reader {
+    fn is_empty(&self) -> bool {
+        self.cursor >= self.values.len()
+    }
+
+    /// Reads a little-endian number of `digits` base 64 digits.
+    fn poll(&mut self, digits: usize) -> Result<u32, String> {
+        if self.cursor + digits > self.values.len() {
+            return Err("unexpected end of data".to_string());
+        }
+        let mut value = 0;
+        for i in (0..digits).rev() {
+            value = value * 64 + self.values[self.cursor + i];
+        }
+        self.cursor += digits;
+        Ok(value)
+    }
+}
+
+fn push(data: &mut Vec<u32>, mut value: u32, digits: usize) {
+    for _ in 0..digits {
+        data.push(value % 64);
+        value /= 64;
+    }
+}
+
+pub fn decode(text: &str) -> Result<Vec<Page>, String> {
+    let text = text.trim();
+    if !text.starts_with(PREFIX) {
+        return Err(format!("only {} fumen is supported", PREFIX));
+    }
+    let mut values = Vec::new();
+    for c in text[PREFIX.len()..].bytes().filter(|&c| c != b'?') {
+        match TABLE.iter().position(|&t| t == c) {
+            Some(value) => values.push(value as u32),
+            None => return Err(format!("bad character `{}`", c as char)),
+        }
+    }
+    let mut reader = Reader { values: values, cursor: 0 };
+
+    let mut pages: Vec<Page> = Vec::new();
+    let mut field = vec![Cell::Empty; BLOCKS];
+    let mut comment = String::new();
+    let mut repeat = 0;
+    while !reader.is_empty() {
+        if repeat > 0 {
+            repeat -= 1;
+        }
+        else {
+            let mut index = 0;
+            let mut is_changed = true;
+            while index < BLOCKS {
+                let run = reader.poll(2)?;
+                if run == UNCHANGED {
+                    is_changed = false;
+                }
+                let diff = (run / BLOCKS as u32) as i32 - 8;
+                for _ in 0..(run % BLOCKS as u32 + 1) {
+                    if index >= BLOCKS {
+                        return Err("field overflows".to_string());
+                    }
+                    field[index] = code_cell((cell_code(field[index]) as i32 + diff) as u32)?;
+                    index += 1;
+                }
+            }
+            if !is_changed {
+                repeat = reader.poll(1)?;
+            }
+        }
+
+        let mut action = reader.poll(3)?;
+        let kind = action % 8;
+        action /= 8;
+        let rotation = ROTATIONS[(action % 4) as usize];
+        action /= 4;
+        let location = action % BLOCKS as u32;
+        action /= BLOCKS as u32;
+        let flag = |action: &mut u32| {
+            let bit = *action % 2 == 1;
+            *action /= 2;
+            bit
+        };
+        let rise = flag(&mut action);
+        let mirror = flag(&mut action);
+        let _colorize = flag(&mut action);
+        let has_comment = flag(&mut action);
+        let lock = !flag(&mut action);
+
+        if has_comment {
+            let length = reader.poll(2)? as usize;
+            let mut escaped = String::new();
+            for _ in 0..(length + 3) / 4 {
+                let mut value = reader.poll(5)?;
+                for _ in 0..4 {
+                    let c = value % COMMENT_BASE;
+                    escaped.push(*COMMENT_TABLE.get(c as usize).unwrap_or(&b' ') as char);
+                    value /= COMMENT_BASE;
+                }
+            }
+            escaped.truncate(length);
+            comment = unescape(&escaped);
+        }
+
+        let operation = match code_cell(kind)? {
+            Cell::Block(kind) => Some(Operation::from_location(kind, rotation, location)),
+            _ => None,
+        };
+        let page = Page {
+            field: field.clone(),
+            operation: operation,
+            comment: comment.clone(),
+            lock: lock,
+            rise: rise,
+            mirror: mirror,
+        };
+        field = page.next_field();
+        pages.push(page);
+    }
+    Ok(pages)
+}
+
+pub fn encode(pages: &[Page]) -> String {
+    let mut diffs = Vec::new();
+    let mut previous = vec![Cell::Empty; BLOCKS];
+    for page in pages.iter() {
+        let mut runs: Vec<(u32, u32)> = Vec::new();
+        for (old, new) in previous.iter().zip(page.field.iter()) {
+            let diff = cell_code(*new) + 8 - cell_code(*old);
+            match runs.last_mut() {
+                Some(&mut (last, ref mut count)) if last == diff => *count += 1,
+                _ => runs.push((diff, 1)),
+            }
+        }
+        diffs.push(runs);
+        previous = page.next_field();
+    }
+    let is_unchanged = |runs: &Vec<(u32, u32)>| runs.len() == 1 && runs[0].0 == 8;
+
+    let mut data = Vec::new();
+    let mut comment = String::new();
+    // Pages left in the current run of unchanged fields, which go out without field data.
+    let mut repeat = 0;
+    for (number, page) in pages.iter().enumerate() {
+        if repeat > 0 {
+            repeat -= 1;
+        }
+        else {
+            for &(diff, count) in diffs[number].iter() {
+                push(&mut data, diff * BLOCKS as u32 + count - 1, 2);
+            }
+            if is_unchanged(&diffs[number]) {
+                repeat = diffs[number + 1..].iter().take(63).take_while(|runs| is_unchanged(runs)).count();
+                push(&mut data, repeat as u32, 1);
+            }
+        }
+
+        let has_comment = page.comment != comment;
+        let (kind, rotation, location) = match page.operation {
+            Some(operation) => (kind_code(operation.kind), rotation_code(operation.rotation), operation.location()),
+            None => (0, 0, 0),
+        };
+        let mut action = !page.lock as u32;
+        action = action * 2 + has_comment as u32;
+        action = action * 2 + (number == 0) as u32;
+        action = action * 2 + page.mirror as u32;
+        action = action * 2 + page.rise as u32;
+        action = action * BLOCKS as u32 + location;
+        action = action * 4 + rotation;
+        action = action * 8 + kind;
+        push(&mut data, action, 3);
+
+        if has_comment {
+            let escaped: String = escape(&page.comment).chars().take(MAX_COMMENT).collect();
+            push(&mut data, escaped.len() as u32, 2);
+            for chunk in escaped.as_bytes().chunks(4) {
+                let mut value = 0;
+                for &c in chunk.iter().rev() {
+                    let code = COMMENT_TABLE.iter().position(|&t| t == c).unwrap_or(0) as u32;
+                    value = value * COMMENT_BASE + code;
+                }
+                push(&mut data, value, 5);
+            }
+            comment = page.comment.clone();
+        }
+    }
+
+    let text: String = data.iter().map(|&value| TABLE[value as usize] as char).collect();
+    let mut result = PREFIX.to_string();
+    for (i, c) in text.chars().enumerate() {
+        // fumen breaks its data with `?` after 42 characters, then every 47
+        if i >= 42 && (i - 42) % 47 == 0 {
+            result.push('?');
+        }
+        result.push(c);
+    }
+    result
+}
+
+/// JavaScript's `escape`, which fumen runs comments through.
+fn escape(text: &str) -> String {
+    let mut result = String::new();
+    for unit in text.encode_utf16() {
+        let is_plain = unit < 128 && {
+            let c = unit as u8 as char;
+            c.is_ascii_alphanumeric() || "@*_+-./".contains(c)
+        };
+        if is_plain {
+            result.push(unit as u8 as char);
+        }
+        else if unit < 256 {
+            result.push_str(&format!("%{:02X}", unit));
+        }
+        else {
+            result.push_str(&format!("%u{:04X}", unit));
+        }
+    }
+    result
+}
+
+fn unescape(text: &str) -> String {
+    let mut units = Vec::new();
+    let mut rest = text;
+    while !rest.is_empty() {
+        let parsed = if rest.starts_with("%u") && rest.len() >= 6 {
+            u16::from_str_radix(&rest[2..6], 16).ok().map(|unit| (unit, 6))
+        }
+        else if rest.starts_with('%') && rest.len() >= 3 {
+            u16::from_str_radix(&rest[1..3], 16).ok().map(|unit| (unit, 3))
+        }
+        else {
+            None
+        };
+        match parsed {
+            Some((unit, length)) => {
+                units.push(unit);
+                rest = &rest[length..];
+            },
+            None => {
+                let c = rest.chars().next().unwrap();
+                let mut buffer = [0; 2];
+                units.extend_from_slice(c.encode_utf16(&mut buffer));
+                rest = &rest[c.len_utf8()..];
+            },
+        }
+    }
+    String::from_utf16_lossy(&units)
+}
+
+fn kind_color(kind: Kind) -> Color {
+    KIND_COLORS.iter().find(|&&(k, _)| k == kind).unwrap().1
+}
+
+/// Our top row has no counterpart in the fumen field.
+fn fumen_y(row: usize) -> Option<usize> {
+    let y = HEIGHT - 1 - row;
+    if y < TOP { Some(y) } else { None }
+}
+
+/// Cells of `main` as the kind of piece they came from, or grey when they came from none.
+fn field_of(main: &Map<Color>, kinds: &Map<Option<Kind>>) -> Vec<Cell> {
+    let mut field = vec![Cell::Empty; BLOCKS];
+    for pos in main.get_iter() {
+        if let Some(y) = fumen_y(pos.y) {
+            field[index(pos.x, y)] = match *kinds.tile(pos) {
+                _ if *main.tile(pos) == Color::default() => Cell::Empty,
+                Some(kind) => Cell::Block(kind),
+                None => Cell::Gray,
+            };
+        }
+    }
+    field
+}
+
+fn cells_of(piece: &Piece) -> Vec<(isize, isize)> {
+    piece.template.0.iter()
+        .map(|delta| (piece.pos.x as isize + delta.dx, HEIGHT as isize - 1 - (piece.pos.y as isize + delta.dy)))
+        .collect()
+}
+
+fn same_cells(a: &[(isize, isize)], b: &[(isize, isize)]) -> bool {
+    a.len() == b.len() && a.iter().all(|cell| b.contains(cell))
+}
+
+/// `None` for a piece partly in our top row, out of fumen's reach.
+fn operation_of(piece: &Piece) -> Option<Operation> {
+    let cells = cells_of(piece);
+    if cells.iter().any(|&(_, y)| y >= TOP as isize) {
+        return None;
+    }
+    let kind = piece.template.1;
+    for &rotation in [Rotation::Spawn, Rotation::Right, Rotation::Reverse, Rotation::Left].iter() {
+        let (dx, dy) = blocks(kind, rotation)[0];
+        for &(x, y) in cells.iter() {
+            let operation = Operation { kind: kind, rotation: rotation, x: x - dx, y: y - dy };
+            if same_cells(&operation.cells(), &cells) {
+                return Some(operation);
+            }
+        }
+    }
+    None
+}
+
+/// Our piece covering the same cells, in one of the orientations of `ruleset`.
+fn piece_of(operation: Operation, ruleset: &Ruleset) -> Option<Piece> {
+    let cells: Vec<(isize, isize)> = operation.cells().iter()
+        .map(|&(x, y)| (x, HEIGHT as isize - 1 - y))
+        .collect();
+    for orientation in 0..4 {
+        let template = ruleset.template(operation.kind, orientation);
+        for &(x, y) in cells.iter() {
+            let DeltaPos { dx, dy } = template.0[0];
+            let (px, py) = (x - dx, y - dy);
+            let candidate: Vec<(isize, isize)> = template.0.iter().map(|d| (px + d.dx, py + d.dy)).collect();
+            if px >= 0 && py >= 0 && same_cells(&candidate, &cells) {
+                let mut piece = Piece::new(template);
+                piece.pos = Pos { x: px as usize, y: py as usize };
+                piece.color = kind_color(operation.kind);
+                return Some(piece);
+            }
+        }
+    }
+    None
+}
+
+impl State {
+    /// Sets the board, falling piece and comment up from the first page of a fumen.
+    pub fn load_fumen(&mut self, text: &str) -> Result<(), String> {
+        match decode(text)?.into_iter().next() {
+            Some(page) => self.load_page(page),
+            None => Err("empty fumen".to_string()),
+        }
+    }
+
+    /// Shows page `index` of a game as a replay would: its board and piece, the pieces of
+    /// the pages after it in the preview and nothing in hold.
+    pub fn show_page(&mut self, pages: &[Page], index: usize) -> Result<(), String> {
+        self.load_page(pages[index].clone())?;
+        for pos in self.hold.get_iter() {
+            *self.hold.tile_mut(pos) = color::PREVIEW;
+        }
+        for pos in self.preview.get_iter() {
+            *self.preview.tile_mut(pos) = color::PREVIEW;
+        }
+        let next = pages[index + 1..].iter().filter_map(|page| page.operation).take(self.config.preview);
+        let mut top = 1;
+        for operation in next {
+            let mut piece = Piece::new(self.ruleset.template(operation.kind, 0));
+            piece.color = kind_color(operation.kind);
+            top += draw_in_panel(&mut self.preview, &piece, top) + 1;
+        }
+        Ok(())
+    }
+
+    fn load_page(&mut self, page: Page) -> Result<(), String> {
+        self.draw_piece(Visible::No);
+        for pos in self.main.get_iter() {
+            let (color, kind) = match fumen_y(pos.y).map(|y| page.field[index(pos.x, y)]) {
+                None | Some(Cell::Empty) => (Color::default(), None),
+                Some(Cell::Block(kind)) => (kind_color(kind), Some(kind)),
+                Some(Cell::Gray) => (color::GARBAGE, None),
+            };
+            *self.main.tile_mut(pos) = color;
+            *self.kinds.tile_mut(pos) = kind;
+        }
+        if let Some(operation) = page.operation {
+            match piece_of(operation, &self.ruleset) {
+                Some(ref piece) if piece.try_into(&self.main).is_ok() => self.piece = *piece,
+                _ => return Err("piece doesn't fit on the board".to_string()),
+            }
+        }
+        self.comment = page.comment;
+        self.draw_piece(Visible::Yes);
+        Ok(())
+    }
+
+    /// Adds a page for the piece about to lock, for exporting the game later.
+    /// The piece must still be drawn on the board.
+    pub fn record_page(&mut self) {
+        self.draw_piece(Visible::No);
+        let page = Page::new(field_of(&self.main, &self.kinds), operation_of(&self.piece));
+        self.draw_piece(Visible::Yes);
+        self.pages.push(page);
+    }
+
+    /// The current board and falling piece as a one page fumen.
+    pub fn board_fumen(&mut self) -> String {
+        self.draw_piece(Visible::No);
+        let mut page = Page::new(field_of(&self.main, &self.kinds), operation_of(&self.piece));
+        self.draw_piece(Visible::Yes);
+        page.lock = false;
+        page.comment = self.comment.clone();
+        encode(&[page])
+    }
+
+    /// Every piece placed so far, one page each, ending on the current board.
+    pub fn game_fumen(&mut self) -> String {
+        let mut pages = self.pages.clone();
+        self.draw_piece(Visible::No);
+        let mut last = Page::new(field_of(&self.main, &self.kinds), operation_of(&self.piece));
+        self.draw_piece(Visible::Yes);
+        last.lock = false;
+        pages.push(last);
+        encode(&pages)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use config::Config;
+    use state::{State, Visible, Pos, HEIGHT};
+    use state::color::{self, Color};
+    use state::ruleset::Rules;
+    use state::template::Kind;
+    use super::{decode, encode, Cell, Rotation, Operation, index};
+
+    const EMPTY: &'static str = "v115@vhAAgH";
+    /// Four rows of grey with the four columns on the right open, a common opener template.
+    const OPENER: &'static str = "v115@9gF8DeF8DeF8DeF8NeAgH";
+    /// The same board with a T flat on the floor of the gap, and as locked on a second page.
+    const WITH_T: &'static str = "v115@9gF8DeF8DeF8DeF8Ne1RJ";
+    const LOCKED_T: &'static str = "v115@9gF8DeF8DeF8DeF8Ne1RJvhAAAe";
+    /// Three empty pages, the last two sharing the first one's field diff.
+    const EMPTY_PAGES: &'static str = "v115@vhCAgHAAAAAA";
+
+    #[test]
+    fn known_strings_survive_a_round_trip() {
+        for &text in [EMPTY, OPENER, WITH_T, LOCKED_T, EMPTY_PAGES].iter() {
+            assert_eq!(encode(&decode(text).unwrap()), text);
+        }
+    }
+
+    #[test]
+    fn decodes_fields_pieces_and_locks() {
+        let pages = decode(LOCKED_T).unwrap();
+        assert_eq!(pages.len(), 2);
+        assert_eq!(pages[0].operation, Some(Operation { kind: Kind::T, rotation: Rotation::Spawn, x: 7, y: 0 }));
+        for y in 0..4 {
+            for x in 0..10 {
+                let cell = if x < 6 { Cell::Gray } else { Cell::Empty };
+                assert_eq!(pages[0].field[index(x, y)], cell);
+            }
+        }
+        assert_eq!(pages[1].operation, None);
+        assert!(!pages[1].lock);
+        for &(x, y) in [(6, 0), (7, 0), (8, 0), (7, 1)].iter() {
+            assert_eq!(pages[1].field[index(x, y)], Cell::Block(Kind::T));
+        }
+        assert!(decode("v115@vh").is_err());
+        assert!(decode("v114@vhAAgH").is_err());
+    }
+
+    #[test]
+    fn unchanged_fields_are_written_as_a_run() {
+        let pages = decode(EMPTY_PAGES).unwrap();
+        assert_eq!(pages.len(), 3);
+        assert!(pages.iter().all(|page| page.field.iter().all(|&cell| cell == Cell::Empty)));
+    }
+
+    #[test]
+    fn a_game_survives_a_round_trip() {
+        let mut state = State::new(&Config::default());
+        for &kind in [Kind::I, Kind::O, Kind::T, Kind::L, Kind::J].iter() {
+            while state.is_between_pieces() {
+                state.tick();
+            }
+            state.draw_piece(Visible::No);
+            state.piece = state.ruleset.piece(kind);
+            state.hard_drop();
+            state.draw_piece(Visible::Yes);
+            state.lock_piece();
+        }
+        let text = state.game_fumen();
+        let pages = decode(&text).unwrap();
+        assert_eq!(pages.len(), 6);
+        for (page, original) in pages.iter().zip(state.pages.iter()) {
+            assert_eq!(page.field, original.field);
+            assert_eq!(page.operation, original.operation);
+        }
+        assert_eq!(encode(&pages), text);
+    }
+
+    #[test]
+    fn load_fumen_sets_the_board_and_the_piece() {
+        let mut state = State::new(&Config::default());
+        state.load_fumen(WITH_T).unwrap();
+        let t = [(6, HEIGHT - 1), (7, HEIGHT - 1), (8, HEIGHT - 1), (7, HEIGHT - 2)];
+        for pos in state.main.get_iter() {
+            let expected = if t.contains(&(pos.x, pos.y)) {
+                state.piece.color
+            }
+            else if pos.y >= HEIGHT - 4 && pos.x < 6 {
+                color::GARBAGE
+            }
+            else {
+                Color::default()
+            };
+            assert_eq!(*state.main.tile(pos), expected, "at {:?}", pos);
+        }
+        assert_eq!(state.piece.template.1, Kind::T);
+        assert_eq!(state.board_fumen(), "v115@9gF8DeF8DeF8DeF8Ne1Rn");
+    }
+
+    #[test]
+    fn game_fumen_has_a_page_per_piece() {
+        let mut state = State::new(&Config::default());
+        state.load_fumen(WITH_T).unwrap();
+        state.lock_piece();
+        let pages = decode(&state.game_fumen()).unwrap();
+        assert_eq!(pages.len(), 2);
+        assert_eq!(encode(&pages[..1]), WITH_T);
+        assert_eq!(pages[1].field[index(7, 1)], Cell::Block(Kind::T));
+        assert!(*state.main.tile(Pos { x: 7, y: HEIGHT - 2 }) != Color::default());
+    }
+
+    #[test]
+    fn exported_cells_keep_their_kind_whatever_the_colours() {
+        let kinds = [Kind::L, Kind::J, Kind::T, Kind::S];
+        for &rules in [Rules::Srs, Rules::Ars, Rules::Nes].iter() {
+            let mut config = Config::default();
+            config.rules = rules;
+            let mut state = State::new(&config);
+            for &kind in kinds.iter() {
+                while state.is_between_pieces() {
+                    state.tick();
+                }
+                state.draw_piece(Visible::No);
+                state.piece = state.ruleset.piece(kind);
+                state.hard_drop();
+                state.draw_piece(Visible::Yes);
+                state.lock_piece();
+            }
+            let pages = decode(&state.game_fumen()).unwrap();
+            let placed: Vec<Kind> = pages.iter().filter_map(|page| page.operation).map(|operation| operation.kind).collect();
+            assert_eq!(&placed[..kinds.len()], &kinds[..], "{:?}", rules);
+            let field = &pages[kinds.len()].field;
+            for &kind in kinds.iter() {
+                assert_eq!(field.iter().filter(|&&cell| cell == Cell::Block(kind)).count(), 4, "{:?} {:?}", rules, kind);
+            }
+            assert!(!field.contains(&Cell::Gray), "{:?}", rules);
+        }
+    }
+}
@@ -0,0 +1,32 @@
+use rand;
+use rand::Rng;
+
+use super::color::{self, Color};
+
+/// Rows with a single hole, where `messiness` is the chance the hole
+/// leaves the column it was in on the previous row.
+pub struct Garbage {
+    hole: usize,
+    width: usize,
+    messiness: f64,
+}
+
+impl Garbage {
+    pub fn new(width: usize, messiness: f64) -> Self {
+        Garbage {
+            hole: rand::thread_rng().gen_range(0, width),
+            width: width,
+            messiness: messiness,
+        }
+    }
+
+    pub fn next_row(&mut self) -> Vec<Color> {
+        let mut rng = rand::thread_rng();
+        if rng.gen::<f64>() < self.messiness {
+            self.hole = (self.hole + rng.gen_range(1, self.width)) % self.width;
+        }
+        (0..self.width)
+            .map(|x| if x == self.hole { Color::default() } else { color::GARBAGE })
+            .collect()
+    }
+}
@@ -0,0 +1,175 @@
+use super::{State, Map, Color, Piece, Queue, Stats, Kind};
+use super::fumen::Page;
+use super::mode::Mode;
+
+/// Everything a lock changes, taken each time a new piece comes in.
+#[derive(Clone)]
+struct Snapshot {
+    main: Map<Color>,
+    kinds: Map<Option<Kind>>,
+    piece: Piece,
+    queue: Queue,
+    held: Option<Piece>,
+    is_hold_used: bool,
+    score: u64,
+    stats: Stats,
+    /// How many fumen pages there were, and the last of them: undo only drops pages
+    /// and redo puts back one at a time.
+    pages: usize,
+    last_page: Option<Page>,
+}
+
+/// Undo and redo stacks of zen mode. The last entry of `past` is the snapshot
+/// of the piece currently falling.
+pub struct History {
+    past: Vec<Snapshot>,
+    future: Vec<Snapshot>,
+}
+
+impl History {
+    pub fn new() -> Self {
+        History {
+            past: Vec::new(),
+            future: Vec::new(),
+        }
+    }
+}
+
+impl State {
+    pub fn record_snapshot(&mut self) {
+        if self.config.mode != Mode::Zen {
+            return;
+        }
+        let snapshot = self.snapshot();
+        self.history.past.push(snapshot);
+        self.history.future.clear();
+    }
+
+    /// Takes back the last locked piece.
+    pub fn undo(&mut self) {
+        if self.history.past.len() < 2 {
+            return;
+        }
+        let current = self.history.past.pop().unwrap();
+        self.history.future.push(current);
+        let previous = self.history.past.last().unwrap().clone();
+        self.restore(previous);
+    }
+
+    pub fn redo(&mut self) {
+        if let Some(next) = self.history.future.pop() {
+            self.history.past.push(next.clone());
+            self.restore(next);
+        }
+    }
+
+    /// Throws away the upcoming pieces for a fresh random sequence.
+    pub fn reset_queue(&mut self) {
+        self.queue = Queue::with_capacity(self.config.preview, self.ruleset);
+        self.redraw_preview();
+        self.replace_snapshot();
+    }
+
+    /// Makes a piece of `kind` come next.
+    pub fn choose_next(&mut self, kind: Kind) {
+        if !self.queue.data.is_empty() {
+            self.queue.data.pop_front();
+        }
+        let piece = self.ruleset.piece(kind);
+        self.queue.data.push_front(piece);
+        self.redraw_preview();
+        self.replace_snapshot();
+    }
+
+    /// Keeps the current snapshot in line with edits made outside of locking.
+    fn replace_snapshot(&mut self) {
+        if self.history.past.pop().is_some() {
+            self.record_snapshot();
+        }
+    }
+
+    fn snapshot(&self) -> Snapshot {
+        Snapshot {
+            main: self.main.clone(),
+            kinds: self.kinds.clone(),
+            piece: self.piece,
+            queue: self.queue.clone(),
+            held: self.held,
+            is_hold_used: self.is_hold_used,
+            score: self.score,
+            stats: self.stats,
+            pages: self.pages.len(),
+            last_page: self.pages.last().cloned(),
+        }
+    }
+
+    fn restore(&mut self, snapshot: Snapshot) {
+        self.main = snapshot.main;
+        self.kinds = snapshot.kinds;
+        self.piece = snapshot.piece;
+        self.queue = snapshot.queue;
+        self.held = snapshot.held;
+        self.is_hold_used = snapshot.is_hold_used;
+        self.score = snapshot.score;
+        self.stats = snapshot.stats;
+        self.pages.truncate(snapshot.pages);
+        if self.pages.len() < snapshot.pages {
+            self.pages.extend(snapshot.last_page);
+        }
+        self.is_last_move_rotation = false;
+        self.redraw_preview();
+        self.redraw_hold();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use config::Config;
+    use state::{State, Visible};
+    use state::color;
+    use state::fumen;
+    use state::mode::Mode;
+    use state::template::Kind;
+
+    fn drop_piece(state: &mut State) {
+        state.draw_piece(Visible::No);
+        state.hard_drop();
+        state.draw_piece(Visible::Yes);
+        state.lock_piece();
+    }
+
+    #[test]
+    fn choose_next_keeps_an_empty_preview() {
+        let mut config = Config::default();
+        config.mode = Mode::Zen;
+        config.preview = 0;
+        let mut state = State::new(&config);
+        state.choose_next(Kind::I);
+        assert!(state.preview.get_iter().all(|pos| *state.preview.tile(pos) == color::PREVIEW));
+
+        drop_piece(&mut state);
+        assert_eq!(state.piece.template.1, Kind::I);
+        assert!(state.queue.data.is_empty());
+    }
+
+    #[test]
+    fn undo_takes_the_page_out_of_the_game() {
+        let mut config = Config::default();
+        config.mode = Mode::Zen;
+        let mut state = State::new(&config);
+        drop_piece(&mut state);
+        drop_piece(&mut state);
+        // a page per locked piece and one for the board now
+        let pages = |state: &mut State| fumen::decode(&state.game_fumen()).unwrap().len();
+        assert_eq!(pages(&mut state), 3);
+        let game = state.game_fumen();
+        state.undo();
+        assert_eq!(pages(&mut state), 2);
+        state.undo();
+        assert_eq!(pages(&mut state), 1);
+        state.redo();
+        assert_eq!(pages(&mut state), 2);
+        state.redo();
+        assert_eq!(state.game_fumen(), game);
+    }
+}
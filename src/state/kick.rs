@@ -0,0 +1,134 @@
+use ::std::str::FromStr;
+
+use super::template::{DeltaPos, Kind};
+
+/// Offsets tried in turn when a half turn doesn't fit where the piece is.
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum HalfTurnKicks {
+    /// The table of TETR.IO's SRS+.
+    SrsPlus,
+    /// NullpoMino's table, reaching up to three columns or rows away.
+    Nullpomino,
+    /// Turns in place or not at all.
+    None,
+}
+
+impl FromStr for HalfTurnKicks {
+    type Err = ();
+
+    fn from_str(s: &str) -> Result<Self, ()> {
+        match s {
+            "srs_plus" => Ok(HalfTurnKicks::SrsPlus),
+            "nullpomino" => Ok(HalfTurnKicks::Nullpomino),
+            "none" => Ok(HalfTurnKicks::None),
+            _ => Err(()),
+        }
+    }
+}
+
+static IN_PLACE: [DeltaPos; 1] = [DeltaPos { dx:  0, dy:  0 }];
+
+// SRS+ tables with `dy` flipped to count down the board, indexed by the orientation turned from
+static SRS_PLUS: [[DeltaPos; 6]; 4] = [
+    [DeltaPos { dx:  0, dy:  0 }, DeltaPos { dx:  0, dy: -1 }, DeltaPos { dx:  1, dy: -1 }, DeltaPos { dx: -1, dy: -1 }, DeltaPos { dx:  1, dy:  0 }, DeltaPos { dx: -1, dy:  0 }],
+    [DeltaPos { dx:  0, dy:  0 }, DeltaPos { dx:  1, dy:  0 }, DeltaPos { dx:  1, dy: -2 }, DeltaPos { dx:  1, dy: -1 }, DeltaPos { dx:  0, dy: -2 }, DeltaPos { dx:  0, dy: -1 }],
+    [DeltaPos { dx:  0, dy:  0 }, DeltaPos { dx:  0, dy:  1 }, DeltaPos { dx: -1, dy:  1 }, DeltaPos { dx:  1, dy:  1 }, DeltaPos { dx: -1, dy:  0 }, DeltaPos { dx:  1, dy:  0 }],
+    [DeltaPos { dx:  0, dy:  0 }, DeltaPos { dx: -1, dy:  0 }, DeltaPos { dx: -1, dy: -2 }, DeltaPos { dx: -1, dy: -1 }, DeltaPos { dx:  0, dy: -2 }, DeltaPos { dx:  0, dy: -1 }],
+];
+
+// NullpoMino's, flipped the same way
+static NULLPOMINO: [[DeltaPos; 12]; 4] = [
+    [DeltaPos { dx:  0, dy:  0 }, DeltaPos { dx:  1, dy:  0 }, DeltaPos { dx:  2, dy:  0 }, DeltaPos { dx:  1, dy: -1 }, DeltaPos { dx:  2, dy: -1 }, DeltaPos { dx: -1, dy:  0 }, DeltaPos { dx: -2, dy:  0 }, DeltaPos { dx: -1, dy: -1 }, DeltaPos { dx: -2, dy: -1 }, DeltaPos { dx:  0, dy:  1 }, DeltaPos { dx:  3, dy:  0 }, DeltaPos { dx: -3, dy:  0 }],
+    [DeltaPos { dx:  0, dy:  0 }, DeltaPos { dx:  0, dy: -1 }, DeltaPos { dx:  0, dy: -2 }, DeltaPos { dx: -1, dy: -1 }, DeltaPos { dx: -1, dy: -2 }, DeltaPos { dx:  0, dy:  1 }, DeltaPos { dx:  0, dy:  2 }, DeltaPos { dx: -1, dy:  1 }, DeltaPos { dx: -1, dy:  2 }, DeltaPos { dx:  1, dy:  0 }, DeltaPos { dx:  0, dy: -3 }, DeltaPos { dx:  0, dy:  3 }],
+    [DeltaPos { dx:  0, dy:  0 }, DeltaPos { dx: -1, dy:  0 }, DeltaPos { dx: -2, dy:  0 }, DeltaPos { dx: -1, dy:  1 }, DeltaPos { dx: -2, dy:  1 }, DeltaPos { dx:  1, dy:  0 }, DeltaPos { dx:  2, dy:  0 }, DeltaPos { dx:  1, dy:  1 }, DeltaPos { dx:  2, dy:  1 }, DeltaPos { dx:  0, dy: -1 }, DeltaPos { dx: -3, dy:  0 }, DeltaPos { dx:  3, dy:  0 }],
+    [DeltaPos { dx:  0, dy:  0 }, DeltaPos { dx:  0, dy: -1 }, DeltaPos { dx:  0, dy: -2 }, DeltaPos { dx:  1, dy: -1 }, DeltaPos { dx:  1, dy: -2 }, DeltaPos { dx:  0, dy:  1 }, DeltaPos { dx:  0, dy:  2 }, DeltaPos { dx:  1, dy:  1 }, DeltaPos { dx:  1, dy:  2 }, DeltaPos { dx: -1, dy:  0 }, DeltaPos { dx:  0, dy: -3 }, DeltaPos { dx:  0, dy:  3 }],
+];
+
+impl HalfTurnKicks {
+    /// Offsets for a half turn from `orientation`, quarter turns clockwise from spawn.
+    pub fn offsets(&self, orientation: usize) -> &'static [DeltaPos] {
+        match *self {
+            HalfTurnKicks::SrsPlus => &SRS_PLUS[orientation % 4],
+            HalfTurnKicks::Nullpomino => &NULLPOMINO[orientation % 4],
+            HalfTurnKicks::None => &IN_PLACE,
+        }
+    }
+}
+
+// SRS offsets of each orientation, flipped like the tables above; the kicks of a quarter turn
+// are the offsets of the orientation turned from less those of the one turned to, test by test
+static SRS_JLSTZ: [[DeltaPos; 5]; 4] = [
+    [DeltaPos { dx:  0, dy:  0 }, DeltaPos { dx:  0, dy:  0 }, DeltaPos { dx:  0, dy:  0 }, DeltaPos { dx:  0, dy:  0 }, DeltaPos { dx:  0, dy:  0 }],
+    [DeltaPos { dx:  0, dy:  0 }, DeltaPos { dx:  1, dy:  0 }, DeltaPos { dx:  1, dy:  1 }, DeltaPos { dx:  0, dy: -2 }, DeltaPos { dx:  1, dy: -2 }],
+    [DeltaPos { dx:  0, dy:  0 }, DeltaPos { dx:  0, dy:  0 }, DeltaPos { dx:  0, dy:  0 }, DeltaPos { dx:  0, dy:  0 }, DeltaPos { dx:  0, dy:  0 }],
+    [DeltaPos { dx:  0, dy:  0 }, DeltaPos { dx: -1, dy:  0 }, DeltaPos { dx: -1, dy:  1 }, DeltaPos { dx:  0, dy: -2 }, DeltaPos { dx: -1, dy: -2 }],
+];
+
+static SRS_I: [[DeltaPos; 5]; 4] = [
+    [DeltaPos { dx:  0, dy:  0 }, DeltaPos { dx: -1, dy:  0 }, DeltaPos { dx:  2, dy:  0 }, DeltaPos { dx: -1, dy:  0 }, DeltaPos { dx:  2, dy:  0 }],
+    [DeltaPos { dx: -1, dy:  0 }, DeltaPos { dx:  0, dy:  0 }, DeltaPos { dx:  0, dy:  0 }, DeltaPos { dx:  0, dy: -1 }, DeltaPos { dx:  0, dy:  2 }],
+    [DeltaPos { dx: -1, dy: -1 }, DeltaPos { dx:  1, dy: -1 }, DeltaPos { dx: -2, dy: -1 }, DeltaPos { dx:  1, dy:  0 }, DeltaPos { dx: -2, dy:  0 }],
+    [DeltaPos { dx:  0, dy: -1 }, DeltaPos { dx:  0, dy: -1 }, DeltaPos { dx:  0, dy: -1 }, DeltaPos { dx:  0, dy:  1 }, DeltaPos { dx:  0, dy: -2 }],
+];
+
+/// Offset of SRS test `test`, 0 to 4, for a piece of `kind` in `orientation`.
+pub fn srs_offset(kind: Kind, orientation: usize, test: usize) -> DeltaPos {
+    match kind {
+        Kind::I => SRS_I[orientation % 4][test],
+        Kind::O => DeltaPos { dx: 0, dy: 0 },
+        _ => SRS_JLSTZ[orientation % 4][test],
+    }
+}
+
+/// Offsets tried for an SRS quarter turn between two orientations.
+pub fn srs_quarter_turn(kind: Kind, from: usize, to: usize) -> Vec<DeltaPos> {
+    (0..5).map(|test| {
+        let (a, b) = (srs_offset(kind, from, test), srs_offset(kind, to, test));
+        DeltaPos { dx: a.dx - b.dx, dy: a.dy - b.dy }
+    }).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use state::template::DeltaPos;
+    use super::HalfTurnKicks;
+
+    fn delta(dx: isize, dy: isize) -> DeltaPos {
+        DeltaPos { dx: dx, dy: dy }
+    }
+
+    #[test]
+    fn tables_start_in_place() {
+        for kicks in [HalfTurnKicks::SrsPlus, HalfTurnKicks::Nullpomino, HalfTurnKicks::None].iter() {
+            for orientation in 0..4 {
+                assert_eq!(kicks.offsets(orientation)[0], delta(0, 0));
+            }
+        }
+    }
+
+    #[test]
+    fn srs_plus_table() {
+        let kicks = HalfTurnKicks::SrsPlus;
+        assert_eq!(kicks.offsets(0).len(), 6);
+        assert_eq!(kicks.offsets(0)[1], delta(0, -1));
+        assert_eq!(kicks.offsets(1)[2], delta(1, -2));
+        assert_eq!(kicks.offsets(2)[3], delta(1, 1));
+        assert_eq!(kicks.offsets(3)[4], delta(0, -2));
+    }
+
+    #[test]
+    fn nullpomino_table() {
+        let kicks = HalfTurnKicks::Nullpomino;
+        assert_eq!(kicks.offsets(0).len(), 12);
+        assert_eq!(kicks.offsets(0)[1], delta(1, 0));
+        assert_eq!(kicks.offsets(0)[3], delta(1, -1));
+        assert_eq!(kicks.offsets(1)[4], delta(-1, -2));
+        assert_eq!(kicks.offsets(2)[9], delta(0, -1));
+        assert_eq!(kicks.offsets(3)[11], delta(0, 3));
+        // a half turn from spawn kicks the opposite way of one back to it
+        for (a, b) in kicks.offsets(0).iter().zip(kicks.offsets(2)) {
+            assert_eq!(*a, delta(-b.dx, -b.dy));
+        }
+        assert_eq!("nullpomino".parse(), Ok(HalfTurnKicks::Nullpomino));
+    }
+}
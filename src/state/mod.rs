@@ -1,21 +1,59 @@
 pub mod color;
 pub mod template;
-mod timer;
+pub mod mode;
+pub mod stats;
+pub mod stopwatch;
+pub mod timer;
+pub mod event;
+pub mod error;
+pub mod kick;
+pub mod ruleset;
+pub mod randomizer;
+pub mod delay;
+mod garbage;
+mod history;
+pub mod puzzle;
+pub mod fumen;
 mod piece;
 mod queue;
 mod map;
 
 use self::color::Color;
 use self::template::{Template, DeltaPos, Kind};
+use self::mode::Mode;
+use self::stats::Stats;
+use self::stopwatch::Stopwatch;
 use self::timer::Timer;
+use self::event::{Event, Events, Reason};
+use self::error::Error;
+use self::delay::{Phase, Initial};
+use self::garbage::Garbage;
+use self::history::History;
+use self::puzzle::Run;
 use self::piece::Piece;
 use self::queue::Queue;
+use self::ruleset::{Ruleset, Rules};
 use self::map::{Map, Pos, Size2};
 
+use ::std::cmp;
+use ::std::time::Duration;
+
+use config::Config;
+
 const MAX_COLLAPSED_ROWS: usize = 4;
+const LINES_PER_LEVEL: u32 = 10;
+/// Tallest stack counted as a perfect clear chance.
+const PC_HEIGHT: usize = 4;
+const DIG_HEIGHT: usize = 10;
+const SURVIVAL_START_MILLIS: u64 = 8000;
+const SURVIVAL_MIN_MILLIS: u64 = 1000;
 pub const PREVIEW_WIDTH: usize = 4;
+pub const HOLD_WIDTH: usize = 4;
 pub const MAIN_WIDTH: usize = 10;
-pub const HEIGHT: usize = 22;
+pub const VISIBLE_HEIGHT: usize = 22;
+/// Hidden rows above the visible board, past the skyline, where pieces come in.
+pub const VANISH_ROWS: usize = 2;
+pub const HEIGHT: usize = VISIBLE_HEIGHT + VANISH_ROWS;
 
 trait Inner<T> {
     fn is_inside(&self, delta: T) -> bool;
@@ -32,39 +70,211 @@ pub enum Visible {
 
 pub struct State {
     pub main: Map<Color>,
+    /// The kind of piece each cell of `main` came from, none for empty and garbage cells.
+    kinds: Map<Option<Kind>>,
     pub preview: Map<Color>,
-    pub queue: Queue<Piece>,
+    pub hold: Map<Color>,
+    pub queue: Queue,
     pub piece: Piece,
+    pub held: Option<Piece>,
+    /// Hold can be used once per piece.
+    is_hold_used: bool,
+    history: History,
+    pub puzzles: Run,
+    /// Pages of every piece locked so far, for exporting the game as a fumen.
+    pages: Vec<fumen::Page>,
+    pub comment: String,
     pub timer: Timer,
+    garbage_timer: Timer,
+    pub stopwatch: Stopwatch,
+    pub stats: Stats,
+    pub config: Config,
+    pub ruleset: Ruleset,
+    garbage: Garbage,
+    /// Garbage rows still to come up from below.
+    garbage_left: usize,
     pub score: u64,
+    pub level: u32,
     pub is_gameover: bool,
+    pub is_complete: bool,
+    /// Whether the board left by the last lock could still be perfect cleared.
+    is_pc_possible: bool,
+    is_last_move_rotation: bool,
+    /// Ticks the piece has rested on the stack, for the ruleset's lock delay.
+    lock_ticks: u32,
+    /// Falling, or waiting on cleared rows or the next piece.
+    phase: Phase,
+    initial: Initial,
+    /// Clearing locks in a row.
+    combo: usize,
+    /// Whether the last clear was a tetris or a spin, for back-to-back.
+    is_b2b_ready: bool,
+    pub events: Events,
 }
 
 impl State {
-    pub fn new() -> Self {
+    pub fn new(config: &Config) -> Self {
+        let ruleset = Ruleset::new(config.rules);
+        let mut queue = Queue::with_capacity(config.preview, ruleset);
+        let first = queue.next().unwrap();
         let mut state = State {
             main: Map::new(Size2 { w: MAIN_WIDTH, h: HEIGHT }),
-            preview: Map::new(Size2 { w: PREVIEW_WIDTH, h: HEIGHT }),
-            queue: Queue::with_capacity(3),
-            piece: Piece::generate(),
+            kinds: Map::new(Size2 { w: MAIN_WIDTH, h: HEIGHT }),
+            preview: Map::new(Size2 { w: PREVIEW_WIDTH, h: VISIBLE_HEIGHT }),
+            hold: Map::new(Size2 { w: HOLD_WIDTH, h: VISIBLE_HEIGHT }),
+            queue: queue,
+            piece: first,
+            held: None,
+            is_hold_used: false,
+            history: History::new(),
+            puzzles: Run::new(Vec::new()),
+            pages: Vec::new(),
+            comment: String::new(),
             timer: Timer::new(),
+            garbage_timer: Timer::new(),
+            stopwatch: Stopwatch::new(),
+            stats: Stats::default(),
+            config: config.clone(),
+            ruleset: ruleset,
+            garbage: Garbage::new(MAIN_WIDTH, config.garbage_messiness),
+            garbage_left: 0,
             score: 0,
+            level: 1,
             is_gameover: false,
+            is_complete: false,
+            is_pc_possible: false,
+            is_last_move_rotation: false,
+            lock_ticks: 0,
+            phase: Phase::Falling,
+            initial: Initial::default(),
+            combo: 0,
+            is_b2b_ready: false,
+            events: Events::new(),
         };
 
+        if !state.config.mode.is_timed() {
+            state.stopwatch.start();
+        }
+        if state.ruleset.rules == Rules::Nes {
+            state.level = state.config.start_level;
+            state.timer.set_threshold(timer::nes_gravity(state.level));
+        }
+        else if state.config.mode == Mode::Marathon {
+            state.timer.set_threshold(timer::level_gravity(1));
+        }
+        if state.config.mode == Mode::Dig {
+            state.garbage_left = state.config.dig_lines;
+            state.add_garbage(DIG_HEIGHT);
+        }
+        if state.config.mode == Mode::Survival {
+            state.garbage_timer.set_threshold(Duration::from_millis(SURVIVAL_START_MILLIS));
+        }
         state.redraw_preview();
+        state.redraw_hold();
+        let _ = state.enter_piece(first);
         state.draw_piece(Visible::Yes);
+        state.record_snapshot();
         state
     }
 
+    /// Gravity runs unless the game is over or a timed mode is still waiting for the first key.
+    pub fn is_running(&self) -> bool {
+        !self.is_gameover && (!self.config.mode.is_timed() || self.stopwatch.is_started())
+    }
+
+    /// One simulation step of the clock; gravity is driven by the caller through `timer`.
+    pub fn tick(&mut self) {
+        self.stopwatch.tick();
+        if self.is_time_up() {
+            self.game_over(Reason::TimeUp);
+        }
+        if !self.is_gameover {
+            self.tick_delay();
+        }
+        if self.config.mode == Mode::Survival {
+            for _ in 0..self.garbage_timer.drops() {
+                self.push_garbage();
+            }
+        }
+    }
+
+    /// Raises the stack and the falling piece by one garbage row, topping out if either
+    /// goes past the ceiling. With `garbage_top_out` off the stack loses its top row instead
+    /// and only a piece that ends up overlapping the stack tops out.
+    pub fn push_garbage(&mut self) {
+        if self.is_gameover {
+            return;
+        }
+        self.draw_piece(Visible::No);
+        let row = self.garbage.next_row();
+        let is_stack_out = self.insert_row(&row).is_err() && self.config.garbage_top_out;
+        let coords = self.piece.try_into(&self.main).unwrap_or_default();
+        let is_piece_at_top = !self.is_between_pieces() && coords.iter().any(|pos| pos.y == 0);
+        let is_piece_out = if self.config.garbage_top_out {
+            is_piece_at_top
+        }
+        else {
+            is_piece_at_top && coords.iter().any(|&pos| *self.main.tile(pos) != Color::default())
+        };
+        if !is_piece_at_top {
+            self.piece.pos.y -= 1;
+        }
+        self.draw_piece(Visible::Yes);
+        if is_stack_out || is_piece_out {
+            self.game_over(Reason::TopOut);
+        }
+
+        // every row comes a twentieth sooner than the one before
+        let interval = self.garbage_timer.threshold() * 19 / 20;
+        self.garbage_timer.set_threshold(cmp::max(interval, Duration::from_millis(SURVIVAL_MIN_MILLIS)));
+    }
+
+    /// Time left in a mode with a time limit.
+    pub fn time_left(&self) -> Option<Duration> {
+        match self.config.mode {
+            Mode::Ultra => {
+                let limit = Duration::from_secs(self.config.ultra_seconds);
+                Some(limit - cmp::min(limit, self.stopwatch.elapsed()))
+            },
+            _ => None,
+        }
+    }
+
+    fn is_time_up(&self) -> bool {
+        self.time_left() == Some(Duration::from_secs(0))
+    }
+
+    /// Text shown above the preview: the clock in timed modes.
+    pub fn hud(&self) -> Option<String> {
+        match self.config.mode {
+            Mode::Endless | Mode::Zen => None,
+            Mode::Puzzle => Some(format!("{}/{}", self.puzzles.index + 1, self.puzzles.pack.len())),
+            Mode::Sprint => Some(stopwatch::format(self.stopwatch.elapsed())),
+            Mode::Ultra => self.time_left().map(stopwatch::format),
+            Mode::Marathon => Some(format!("{}", self.level)),
+            Mode::Dig | Mode::Survival => Some(stopwatch::format(self.stopwatch.elapsed())),
+        }
+    }
+
+    pub fn press_key(&mut self) {
+        self.stopwatch.start();
+        self.stats.keys += 1;
+    }
+
+    /// Draws or erases the falling piece; there's none while the next one is on its way.
     pub fn draw_piece(&mut self, visible: Visible) {
+        if self.is_between_pieces() {
+            return;
+        }
         let piece = self.piece.clone();
-        if let Some(coords) = piece.try_into(&self.main) {
+        let (color, kind) = match visible {
+            Visible::No => (Color::default(), None),
+            Visible::Yes => (piece.color, Some(piece.template.1)),
+        };
+        if let Ok(coords) = piece.try_into(&self.main) {
             for pos in coords {
-                *self.main.tile_mut(pos) = match visible {
-                    Visible::No => Color::default(),
-                    Visible::Yes => piece.color,
-                };
+                *self.main.tile_mut(pos) = color;
+                *self.kinds.tile_mut(pos) = kind;
             }
         }
     }
@@ -73,86 +283,325 @@ impl State {
         for pos in self.preview.get_iter() {
             *self.preview.tile_mut(pos) = color::PREVIEW;
         }
-        let mut bottom = 1;
-        for piece in self.queue.data.iter() {
-            let mut piece = piece.clone();
-            if piece.template.1 == Kind::I {
-                bottom -= 1;
-            }
-            piece.pos = Pos { x: 1, y: bottom };
-            let coords = piece.try_into(&self.preview).unwrap();
-            bottom = coords.iter().map(|pos| pos.y).max().unwrap() + 3;
-            for pos in coords {
-                *self.preview.tile_mut(pos) = piece.color;
-            }
+        let mut top = 1;
+        for piece in self.queue.data.iter().take(self.config.preview) {
+            top += draw_in_panel(&mut self.preview, piece, top) + 1;
         }
     }
 
-    pub fn spawn_piece(&mut self) -> Result<(), ()> {
-        let piece = self.queue.next();
-        if let Some(coords) = piece.try_into(&self.main) {
-            self.redraw_preview();
+    pub fn redraw_hold(&mut self) {
+        for pos in self.hold.get_iter() {
+            *self.hold.tile_mut(pos) = color::PREVIEW;
+        }
+        if let Some(ref piece) = self.held {
+            draw_in_panel(&mut self.hold, piece, 1);
+        }
+    }
+
+    pub fn spawn_piece(&mut self) -> Result<(), Error> {
+        let piece = match self.queue.next() {
+            Some(piece) => piece,
+            None => return Err(Error::NoPieces),
+        };
+        self.redraw_preview();
+        self.is_hold_used = false;
+        self.enter_piece(piece)
+    }
+
+    /// Swaps the falling piece with the held one, or with the next piece if nothing is held yet.
+    /// Nothing happens if nothing is held and a puzzle's queue has run out.
+    pub fn hold_piece(&mut self) {
+        if self.is_hold_used || (self.held.is_none() && self.queue.is_empty()) {
+            return;
+        }
+        self.draw_piece(Visible::No);
+        let held = self.held.take();
+        self.held = Some(self.piece.respawned(&self.ruleset));
+        let entered = match held {
+            Some(piece) => self.enter_piece(piece),
+            None => self.spawn_piece(),
+        };
+        self.is_hold_used = true;
+        self.redraw_hold();
+        self.emit(Event::Hold);
+        if entered.is_ok() {
+            self.draw_piece(Visible::Yes);
+        }
+        else if self.config.mode == Mode::Puzzle {
+            self.fail_puzzle();
+        }
+    }
 
-            let is_colliding = coords
-                .iter()
-                .map(|&pos| *self.main.tile(pos))
-                .any(|color| color != Color::default());
+    /// Puts `piece` on the board as the falling piece. Blocked by the stack it ends
+    /// the game, apart from zen where the board is cleared to make room instead, and
+    /// puzzles where it fails the puzzle, which the caller sees to.
+    fn enter_piece(&mut self, piece: Piece) -> Result<(), Error> {
+        let coords = piece.try_into(&self.main)?;
+        let is_colliding = coords
+            .iter()
+            .map(|&pos| *self.main.tile(pos))
+            .any(|color| color != Color::default());
 
-            if is_colliding {
-                self.is_gameover = true;
-                Err(())
-            }
-            else {
-                self.piece = piece;
-                Ok(())
+        if is_colliding && self.config.mode == Mode::Zen {
+            self.clear_board();
+        }
+        else if is_colliding {
+            if self.config.mode != Mode::Puzzle {
+                self.game_over(Reason::BlockOut);
             }
+            return Err(Error::BlockOut);
         }
-        else {
-            Err(())
+        self.piece = piece;
+        self.is_last_move_rotation = false;
+        self.lock_ticks = 0;
+        // pieces come in above the skyline and drop straight into view if there's room
+        let mut dropped = piece;
+        dropped.pos.y += 1;
+        if self.fit(&dropped).is_ok() {
+            self.piece = dropped;
         }
+        self.emit(Event::PieceSpawned(piece.template.1));
+        Ok(())
     }
 
-    pub fn move_piece(&mut self, delta: DeltaPos) -> Result<(), ()> {
+    pub fn is_board_empty(&self) -> bool {
+        self.main.get_iter().all(|pos| *self.main.tile(pos) == Color::default())
+    }
+
+    fn clear_board(&mut self) {
+        for pos in self.main.get_iter() {
+            *self.main.tile_mut(pos) = Color::default();
+            *self.kinds.tile_mut(pos) = None;
+        }
+    }
+
+    pub fn move_piece(&mut self, delta: DeltaPos) -> Result<(), Error> {
         let mut moved = self.piece;
         let mut pos = moved.pos;
         pos.x = (pos.x as isize + delta.dx) as usize;
         pos.y = (pos.y as isize + delta.dy) as usize;
         moved.pos = pos;
 
-        if self.is_inside(moved) && !self.is_colliding(moved) {
-            self.piece.pos = moved.pos;
-            Ok(())
+        self.fit(&moved)?;
+        self.piece.pos = moved.pos;
+        self.is_last_move_rotation = false;
+        if delta.dy > 0 {
+            self.lock_ticks = 0;
+        }
+        self.emit(Event::Moved(delta));
+        Ok(())
+    }
+
+    pub fn hard_drop(&mut self) {
+        let down = DeltaPos { dx: 0, dy: 1 };
+        let mut rows = 0;
+        while self.move_piece(down).is_ok() {
+            rows += 1;
+        }
+        self.emit(Event::Dropped(rows));
+    }
+
+    pub fn is_grounded(&self) -> bool {
+        let mut below = self.piece;
+        below.pos.y += 1;
+        self.fit(&below).is_err()
+    }
+
+    /// Counts a tick towards locking the piece, given whether gravity was just blocked by
+    /// the stack. Without a lock delay only that locks it.
+    pub fn is_lock_due(&mut self, is_blocked: bool) -> bool {
+        match self.ruleset.lock_delay {
+            None => is_blocked,
+            Some(delay) => {
+                if !self.is_grounded() {
+                    self.lock_ticks = 0;
+                    return false;
+                }
+                self.lock_ticks += 1;
+                self.lock_ticks >= delay
+            },
+        }
+    }
+
+    /// Finishes off a piece that has come to rest: clears rows, checks the mode's goal
+    /// and brings in the next piece, after the line clear delay if it fills any rows.
+    pub fn lock_piece(&mut self) {
+        let bottom = self.piece.try_into(&self.main).unwrap_or_default().iter().map(|pos| pos.y).max();
+        let height = HEIGHT - 1 - bottom.unwrap_or(0);
+        self.record_page();
+        self.stats.pieces += 1;
+        self.emit(Event::Locked(self.piece.template.1));
+        if let Err(Error::LockOut) = self.check_lock_out() {
+            match self.config.mode {
+                Mode::Zen => self.clear_board(),
+                Mode::Puzzle => {
+                    self.fail_puzzle();
+                    return;
+                },
+                _ => {
+                    self.game_over(Reason::LockOut);
+                    return;
+                },
+            }
+        }
+        let is_tspin = self.is_tspin();
+        let entry = self.entry_delay(height);
+        let delay = self.line_clear_delay();
+        if delay > 0 && !self.filled_rows().is_empty() {
+            self.phase = Phase::Clearing { ticks: delay, entry: entry, is_tspin: is_tspin };
         }
         else {
-            Err(())
+            self.clear_rows(is_tspin, entry);
         }
     }
 
-    pub fn rotate_piece(&mut self) {
-        let mut rotated = self.piece;
-        rotated.template = rotated.template.rotate_right();
-        if self.is_inside(rotated) && !self.is_colliding(rotated) {
-            self.piece = rotated;
+    /// Collapses the full rows left by a lock and scores them, then checks the mode's goal
+    /// and has the next piece come in after `entry` ticks.
+    fn clear_rows(&mut self, is_tspin: bool, entry: u32) {
+        let cleared = self.filled_rows();
+        let garbage = cleared.iter().filter(|&&y| self.is_garbage_row(y)).count();
+        let mut cells = Vec::with_capacity(cleared.len() * MAIN_WIDTH);
+        for &y in cleared.iter() {
+            cells.extend((0..MAIN_WIDTH).map(|x| *self.main.tile(Pos { x: x, y: y })));
+        }
+        let rows = self.collapse_rows();
+        self.stats.count_clear(rows, is_tspin);
+        self.count_combo(cleared, cells, is_tspin);
+        self.count_perfect_clear(rows);
+        self.stats.garbage += garbage;
+        self.update_speed(rows);
+        if self.config.mode == Mode::Dig {
+            self.add_garbage(garbage);
+        }
+        if !self.is_gameover && self.config.mode == Mode::Puzzle {
+            self.advance_puzzle(rows, is_tspin);
+        }
+        else if !self.is_gameover {
+            if self.is_goal_reached() {
+                self.game_over(Reason::Finished);
+            }
+            else {
+                self.enter_after(entry);
+            }
         }
     }
 
-    pub fn hard_drop(&mut self) {
-        let down = DeltaPos { dx: 0, dy: 1 };
-        while self.move_piece(down).is_ok() {};
+    fn count_combo(&mut self, rows: Vec<usize>, cells: Vec<Color>, is_tspin: bool) {
+        if rows.is_empty() {
+            self.combo = 0;
+            return;
+        }
+        let is_hard = rows.len() == 4 || is_tspin;
+        let b2b = is_hard && self.is_b2b_ready;
+        self.is_b2b_ready = is_hard;
+        self.emit(Event::LinesCleared { rows: rows, cells: cells, spin: is_tspin, combo: self.combo, b2b: b2b });
+        self.combo += 1;
     }
 
-    pub fn collapse_rows(&mut self) {
-        let mut filled_rows = self.filled_rows();
-        if filled_rows.len() > 0 {
-            self.timer.lower_threshold();
-        }
-        self.score += match filled_rows.len() {
-            1 => 40,
-            2 => 100,
-            3 => 300,
-            4 => 1200,
-            _ => 0,
+    fn count_perfect_clear(&mut self, rows: usize) {
+        let is_perfect_clear = rows > 0 && self.is_board_empty();
+        if is_perfect_clear {
+            self.stats.perfect_clears += 1;
+            self.score += self.config.perfect_clear_bonus;
+            self.emit(Event::PerfectClear);
+        }
+        // A clear from a board that wasn't counted yet, such as a puzzle's, was a chance as well.
+        let is_possible = self.is_pc_possible();
+        if (is_possible || is_perfect_clear) && !self.is_pc_possible {
+            self.stats.pc_chances += 1;
+        }
+        self.is_pc_possible = is_possible;
+    }
+
+    /// A non-empty stack no more than `PC_HEIGHT` rows tall, with a row count at or above it
+    /// whose empty cells whole pieces could fill.
+    fn is_pc_possible(&self) -> bool {
+        let size = self.main.size();
+        let filled = self.main.get_iter().filter(|&pos| *self.main.tile(pos) != Color::default()).count();
+        let top = (0..size.h).find(|&y| (0..size.w).any(|x| *self.main.tile(Pos { x: x, y: y }) != Color::default()));
+        let height = match top {
+            Some(y) => size.h - y,
+            None => return false,
         };
+        (height..PC_HEIGHT + 1).any(|rows| (rows * size.w - filled) % 4 == 0)
+    }
+
+    fn update_speed(&mut self, rows: usize) {
+        if self.ruleset.rules == Rules::Nes {
+            let level = Ruleset::nes_level(self.config.start_level, self.stats.lines as u32);
+            if level != self.level {
+                self.level = level;
+                self.timer.set_threshold(timer::nes_gravity(level));
+                self.emit(Event::LevelUp(level));
+            }
+            return;
+        }
+        match self.config.mode {
+            Mode::Marathon => {
+                let level = 1 + self.stats.lines as u32 / LINES_PER_LEVEL;
+                if level != self.level {
+                    self.level = level;
+                    self.timer.set_threshold(timer::level_gravity(level));
+                    self.emit(Event::LevelUp(level));
+                }
+            },
+            _ => if rows > 0 {
+                self.timer.lower_threshold();
+            },
+        }
+    }
+
+    fn is_goal_reached(&self) -> bool {
+        match self.config.mode {
+            Mode::Endless | Mode::Ultra | Mode::Survival | Mode::Zen | Mode::Puzzle => false,
+            Mode::Dig => self.stats.garbage >= self.config.dig_lines,
+            Mode::Sprint => self.stats.lines >= self.config.sprint_lines,
+            Mode::Marathon => {
+                let levels = self.config.marathon_levels;
+                levels > 0 && self.stats.lines as u32 >= levels * LINES_PER_LEVEL
+            },
+        }
+    }
+
+    /// Three-corner rule: a T that got into place by rotating, with at least three
+    /// of the four cells diagonal to its centre blocked.
+    fn is_tspin(&self) -> bool {
+        if self.piece.template.1 != Kind::T || !self.is_last_move_rotation {
+            return false;
+        }
+        let center = self.piece.pos;
+        let corners = [(-1, -1), (1, -1), (-1, 1), (1, 1)];
+        let blocked = corners.iter().filter(|&&(dx, dy)| {
+            let x = center.x as isize + dx;
+            let y = center.y as isize + dy;
+            if x < 0 || y < 0 {
+                return true;
+            }
+            let pos = Pos { x: x as usize, y: y as usize };
+            !self.main.is_inside(pos) || *self.main.tile(pos) != Color::default()
+        }).count();
+        blocked >= 3
+    }
+
+    /// Key the personal best of the current mode is stored under.
+    pub fn record_key(&self) -> Option<String> {
+        match self.config.mode {
+            Mode::Endless | Mode::Zen | Mode::Puzzle => None,
+            Mode::Sprint => Some(format!("sprint{}", self.config.sprint_lines)),
+            Mode::Ultra => Some(format!("ultra{}", self.config.ultra_seconds)),
+            Mode::Marathon => match self.config.marathon_levels {
+                0 => Some("marathon_endless".to_string()),
+                levels => Some(format!("marathon{}", levels)),
+            },
+            Mode::Dig => Some(format!("dig{}", self.config.dig_lines)),
+            Mode::Survival => Some("survival".to_string()),
+        }
+    }
+
+    pub fn collapse_rows(&mut self) -> usize {
+        let mut filled_rows = self.filled_rows();
+        let count = filled_rows.len();
+        self.score += self.ruleset.clear_score(count, self.level);
 
         while let Some(row) = filled_rows.pop() {
             self.remove_row(row);
@@ -160,18 +609,55 @@ impl State {
                 *row += 1;
             }
         }
+        count
+    }
+
+    /// Pushes up to `count` garbage rows in from the bottom, ending the game if the stack
+    /// is pushed through the ceiling.
+    fn add_garbage(&mut self, count: usize) {
+        let count = cmp::min(count, self.garbage_left);
+        self.garbage_left -= count;
+        for _ in 0..count {
+            let row = self.garbage.next_row();
+            if self.insert_row(&row).is_err() && self.config.garbage_top_out {
+                self.game_over(Reason::TopOut);
+                break;
+            }
+        }
     }
 
-    fn is_colliding(&self, piece: Piece) -> bool {
-        if let (Some(old_coords), Some(new_coords)) = (self.piece.try_into(&self.main), piece.try_into(&self.main)) {
-            new_coords
-                .iter()
-                .filter(|pos| !old_coords.contains(pos))
-                .map(|&pos| *self.main.tile(pos))
-                .any(|color| color != Color::default())
+    /// Checks that `piece` is on the board and clear of the stack, not counting
+    /// the cells of the falling piece itself.
+    fn fit(&self, piece: &Piece) -> Result<(), Error> {
+        let own = self.piece.try_into(&self.main).unwrap_or_default();
+        let cells: Vec<Pos> = piece.try_into(&self.main)?
+            .into_iter()
+            .filter(|pos| !own.contains(pos) && *self.main.tile(*pos) != Color::default())
+            .collect();
+        if cells.is_empty() {
+            Ok(())
+        }
+        else {
+            Err(Error::Collision { cells: cells })
+        }
+    }
+
+    /// A piece coming to rest with none of its cells below the skyline is a lock out,
+    /// or with any of them above it if `partial_lock_out` is on.
+    fn check_lock_out(&self) -> Result<(), Error> {
+        let coords = self.piece.try_into(&self.main)?;
+        let is_above = |pos: &Pos| pos.y < VANISH_ROWS;
+        let is_out = if self.config.partial_lock_out {
+            coords.iter().any(is_above)
+        }
+        else {
+            coords.iter().all(is_above)
+        };
+        if is_out {
+            Err(Error::LockOut)
         }
         else {
-            true
+            Ok(())
         }
     }
 
@@ -195,6 +681,32 @@ impl State {
         true
     }
 
+    fn is_garbage_row(&self, y: usize) -> bool {
+        (0..self.main.size().w).any(|x| *self.main.tile(Pos { x: x, y: y }) == color::GARBAGE)
+    }
+
+    /// Inverse of `remove_row`: shifts the whole board up and puts `tiles` in the bottom row.
+    /// Fails if something in the top row was pushed off the board.
+    /// The falling piece must be undrawn first or it gets carried along with the stack.
+    fn insert_row(&mut self, tiles: &[Color]) -> Result<(), Error> {
+        let is_overflowing = (0..self.main.size().w)
+            .any(|x| *self.main.tile(Pos { x: x, y: 0 }) != Color::default());
+        let bottom = self.main.size().h - 1;
+        for row in 0..bottom {
+            self.move_row_up(row);
+        }
+        for (x, &color) in tiles.iter().enumerate() {
+            *self.main.tile_mut(Pos { x: x, y: bottom }) = color;
+            *self.kinds.tile_mut(Pos { x: x, y: bottom }) = None;
+        }
+        if is_overflowing {
+            Err(Error::TopOut)
+        }
+        else {
+            Ok(())
+        }
+    }
+
     fn remove_row(&mut self, y: usize) {
         for row in (1..y+1).rev() {
             self.move_row_down(row);
@@ -202,6 +714,7 @@ impl State {
         for x in 0..self.main.size().w {
             let pos = Pos {x: x, y: 0};
             *self.main.tile_mut(pos) = Color::default();
+            *self.kinds.tile_mut(pos) = None;
         }
     }
 
@@ -209,33 +722,198 @@ impl State {
         for x in 0..self.main.size().w {
             let (old_pos, new_pos) = (Pos { x: x, y: y-1 }, Pos { x: x, y: y });
             *self.main.tile_mut(new_pos) = *self.main.tile(old_pos);
+            *self.kinds.tile_mut(new_pos) = *self.kinds.tile(old_pos);
+        }
+    }
+
+    fn move_row_up(&mut self, y: usize) {
+        for x in 0..self.main.size().w {
+            let (old_pos, new_pos) = (Pos { x: x, y: y+1 }, Pos { x: x, y: y });
+            *self.main.tile_mut(new_pos) = *self.main.tile(old_pos);
+            *self.kinds.tile_mut(new_pos) = *self.kinds.tile(old_pos);
         }
     }
 
+    /// Size of what's shown, which leaves out the vanish zone.
     pub fn dim(&self) -> Size2 {
         let main = self.main.size;
         let preview = self.preview.size;
-        Size2 { w: main.w + preview.w, h: main.h }
+        let hold = self.hold.size;
+        Size2 { w: hold.w + main.w + preview.w, h: main.h - VANISH_ROWS }
     }
+}
 
-    pub fn box_width(&self) -> f32 {
-        1.0 / self.dim().w as f32
+/// Draws `piece` centred across `panel` with its top at row `top`, returns its height.
+fn draw_in_panel(panel: &mut Map<Color>, piece: &Piece, top: usize) -> usize {
+    let mut piece = piece.clone();
+    let (min, max) = piece.template.bounds();
+    let width = (max.dx - min.dx + 1) as usize;
+    let height = (max.dy - min.dy + 1) as usize;
+    piece.pos = Pos {
+        x: (((panel.size().w - width) / 2) as isize - min.dx) as usize,
+        y: (top as isize - min.dy) as usize,
+    };
+    for pos in piece.try_into(panel).unwrap() {
+        *panel.tile_mut(pos) = piece.color;
     }
+    height
+}
+
+#[cfg(test)]
+mod tests {
+    use config::Config;
+    use super::{State, Visible, Queue, Pos, HEIGHT, VANISH_ROWS, MAIN_WIDTH};
+    use super::color;
+    use super::error::Error;
+    use super::event::{Event, Reason};
+    use super::mode::Mode;
+    use super::template::Kind;
 
-    pub fn box_height(&self) -> f32 {
-        1.0 / self.dim().h as f32
+    fn state(mode: Mode) -> State {
+        let mut config = Config::default();
+        config.mode = mode;
+        State::new(&config)
+    }
+
+    fn reason(state: &mut State) -> Option<Reason> {
+        state.take_events().into_iter().filter_map(|event| match event {
+            Event::GameOver { reason } => Some(reason),
+            _ => None,
+        }).next()
+    }
+
+    fn fill_row(state: &mut State, y: usize) {
+        for x in 0..MAIN_WIDTH {
+            *state.main.tile_mut(Pos { x: x, y: y }) = color::GARBAGE;
+        }
+    }
+
+    /// Locks an O piece against the left wall, out of the way of the next one, with its
+    /// top row at `top`.
+    fn lock_o_at(state: &mut State, top: usize) {
+        state.draw_piece(Visible::No);
+        let mut piece = state.ruleset.piece(Kind::O);
+        piece.pos.x = 1;
+        piece.pos.y = HEIGHT;
+        while piece.try_into(&state.main).map(|cells| cells.iter().all(|pos| pos.y > top)).unwrap_or(true) {
+            piece.pos.y -= 1;
+        }
+        state.piece = piece;
+        state.draw_piece(Visible::Yes);
+        state.lock_piece();
+    }
+
+    #[test]
+    fn blocked_spawn_is_a_block_out() {
+        let mut state = state(Mode::Sprint);
+        state.draw_piece(Visible::No);
+        for y in 0..VANISH_ROWS + 2 {
+            fill_row(&mut state, y);
+        }
+        state.take_events();
+        assert_eq!(state.spawn_piece(), Err(Error::BlockOut));
+        assert!(state.is_gameover);
+        assert_eq!(reason(&mut state), Some(Reason::BlockOut));
+    }
+
+    #[test]
+    fn locking_in_the_vanish_zone_is_a_lock_out() {
+        let mut state = state(Mode::Sprint);
+        lock_o_at(&mut state, VANISH_ROWS - 2);
+        assert!(state.is_gameover);
+        assert_eq!(reason(&mut state), Some(Reason::LockOut));
+    }
+
+    #[test]
+    fn partial_lock_out_needs_the_setting() {
+        let mut state = state(Mode::Sprint);
+        lock_o_at(&mut state, VANISH_ROWS - 1);
+        assert!(!state.is_gameover);
+
+        let mut config = Config::default();
+        config.mode = Mode::Sprint;
+        config.partial_lock_out = true;
+        let mut state = State::new(&config);
+        lock_o_at(&mut state, VANISH_ROWS - 1);
+        assert_eq!(reason(&mut state), Some(Reason::LockOut));
+    }
+
+    #[test]
+    fn garbage_through_the_ceiling_is_a_top_out() {
+        let mut state = state(Mode::Survival);
+        *state.main.tile_mut(Pos { x: 0, y: 0 }) = color::GARBAGE;
+        state.push_garbage();
+        assert!(state.is_gameover);
+        assert_eq!(reason(&mut state), Some(Reason::TopOut));
+
+        let mut config = Config::default();
+        config.mode = Mode::Survival;
+        config.garbage_top_out = false;
+        let mut state = State::new(&config);
+        *state.main.tile_mut(Pos { x: 0, y: 0 }) = color::GARBAGE;
+        state.push_garbage();
+        assert!(!state.is_gameover);
+    }
+
+    #[test]
+    fn ultra_runs_out_of_time() {
+        let mut config = Config::default();
+        config.mode = Mode::Ultra;
+        config.ultra_seconds = 1;
+        let mut state = State::new(&config);
+        state.press_key();
+        for _ in 0..1000 {
+            if state.is_gameover {
+                break;
+            }
+            state.tick();
+        }
+        assert!(state.is_complete);
+        assert_eq!(reason(&mut state), Some(Reason::TimeUp));
+    }
+
+    #[test]
+    fn sprint_finishes_at_its_line_target() {
+        let mut config = Config::default();
+        config.mode = Mode::Sprint;
+        config.sprint_lines = 1;
+        let mut state = State::new(&config);
+        state.draw_piece(Visible::No);
+        state.piece = state.ruleset.piece(Kind::I);
+        state.hard_drop();
+        let cells = state.piece.try_into(&state.main).unwrap();
+        for x in 0..MAIN_WIDTH {
+            let pos = Pos { x: x, y: HEIGHT - 1 };
+            if !cells.contains(&pos) {
+                *state.main.tile_mut(pos) = color::GARBAGE;
+            }
+        }
+        state.draw_piece(Visible::Yes);
+        state.lock_piece();
+        for _ in 0..100 {
+            if state.is_gameover {
+                break;
+            }
+            state.tick();
+        }
+        assert!(state.is_complete);
+        assert_eq!(reason(&mut state), Some(Reason::Finished));
+    }
+
+    #[test]
+    fn empty_fixed_queue_has_no_pieces() {
+        let mut state = state(Mode::Puzzle);
+        state.queue = Queue::fixed(&[], state.ruleset);
+        assert_eq!(state.spawn_piece(), Err(Error::NoPieces));
+        assert!(!state.is_gameover);
     }
-}
 
-impl Inner<Piece> for State {
-    fn is_inside(&self, piece: Piece) -> bool {
-        let kind = piece.template;
-        let pos = piece.pos;
-        
-        kind.0.iter().all(|tile| {
-            let dx = tile.dx + pos.x as isize;
-            let dy = tile.dy + pos.y as isize;
-            0 <= dx && 0 <= dy && dx < self.dim().w as isize && dy < self.dim().h as isize
-        })
+    #[test]
+    fn reasons_and_errors_read_plainly() {
+        let reasons = [Reason::Finished, Reason::TimeUp, Reason::BlockOut, Reason::LockOut, Reason::TopOut];
+        let names: Vec<String> = reasons.iter().map(|reason| reason.to_string()).collect();
+        assert_eq!(names, ["finished", "time up", "block out", "lock out", "top out"]);
+        assert_eq!(Error::NoPieces.to_string(), "no pieces left");
+        assert_eq!(Error::Collision { cells: vec![Pos { x: 0, y: 0 }] }.to_string(), "collides with the stack at 1 cells");
     }
 }
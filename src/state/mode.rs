@@ -0,0 +1,64 @@
+use ::std::str::FromStr;
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum Mode {
+    Endless,
+    Sprint,
+    Ultra,
+    Marathon,
+    Dig,
+    Survival,
+    Zen,
+    Puzzle,
+}
+
+impl FromStr for Mode {
+    type Err = ();
+
+    fn from_str(s: &str) -> Result<Self, ()> {
+        match s {
+            "endless" => Ok(Mode::Endless),
+            "sprint" => Ok(Mode::Sprint),
+            "ultra" => Ok(Mode::Ultra),
+            "marathon" => Ok(Mode::Marathon),
+            "dig" => Ok(Mode::Dig),
+            "survival" => Ok(Mode::Survival),
+            "zen" => Ok(Mode::Zen),
+            "puzzle" => Ok(Mode::Puzzle),
+            _ => Err(()),
+        }
+    }
+}
+
+pub const MODES: [Mode; 8] = [
+    Mode::Endless, Mode::Sprint, Mode::Ultra, Mode::Marathon,
+    Mode::Dig, Mode::Survival, Mode::Zen, Mode::Puzzle,
+];
+
+impl Mode {
+    /// Name in the config, as `from_str` takes it.
+    pub fn name(&self) -> &'static str {
+        match *self {
+            Mode::Endless => "endless",
+            Mode::Sprint => "sprint",
+            Mode::Ultra => "ultra",
+            Mode::Marathon => "marathon",
+            Mode::Dig => "dig",
+            Mode::Survival => "survival",
+            Mode::Zen => "zen",
+            Mode::Puzzle => "puzzle",
+        }
+    }
+
+    /// Timed modes hold gravity and the clock back until the first key press.
+    pub fn is_timed(&self) -> bool {
+        match *self {
+            Mode::Endless | Mode::Marathon | Mode::Survival | Mode::Zen | Mode::Puzzle => false,
+            Mode::Sprint | Mode::Ultra | Mode::Dig => true,
+        }
+    }
+
+    pub fn has_gravity(&self) -> bool {
+        *self != Mode::Zen && *self != Mode::Puzzle
+    }
+}
@@ -1,4 +1,8 @@
 use super::{Inner, Generate, Template, Pos, Color, Map, MAX_COLLAPSED_ROWS};
+use super::error::Error;
+use super::ruleset::Ruleset;
+
+const SPAWN_POS: Pos = Pos { x: 4, y: 1 };
 
 #[derive(Clone, Copy, Debug)]
 pub struct Piece {
@@ -7,34 +11,41 @@ pub struct Piece {
     pub color: Color,
 }
 
-impl Generate for Piece {
-    fn generate() -> Self {
+impl Piece {
+    pub fn new(template: Template) -> Self {
         Piece {
-            template: Template::generate(),
-            pos: Pos { x: 4, y: 1 },
+            template: template,
+            pos: SPAWN_POS,
             color: Color::generate(),
         }
     }
-}
 
-impl Piece {
-    pub fn try_into(&self, map: &Map<Color>) -> Option<Vec<Pos>> {
+    /// Same piece back in its spawn position and orientation, as when it comes out of hold.
+    pub fn respawned(&self, ruleset: &Ruleset) -> Self {
+        Piece {
+            template: ruleset.template(self.template.1, 0),
+            pos: SPAWN_POS,
+            color: self.color,
+        }
+    }
+
+    pub fn try_into(&self, map: &Map<Color>) -> Result<Vec<Pos>, Error> {
         let mut result = Vec::<Pos>::with_capacity(MAX_COLLAPSED_ROWS);
         for delta in self.template.0.iter() {
             let dx = delta.dx + self.pos.x as isize;
             let dy = delta.dy + self.pos.y as isize;
             if dx < 0 || dy < 0 {
-                return None;
+                return Err(Error::OutOfBounds);
             }
             let pos = Pos { x: dx as usize, y: dy as usize};
             if map.is_inside(pos) {
                 result.push(pos);
             }
             else {
-                return None;
+                return Err(Error::OutOfBounds);
             }
         }
 
-        Some(result)
+        Ok(result)
     }
 }
\ No newline at end of file
@@ -0,0 +1,284 @@
+//! Puzzle packs: a plain text file holding any number of puzzles, each made of
+//!
+//! ```text
+//! puzzle Name of the puzzle
+//! goal lines 2  # or: goal perfect_clear, goal tspin_double, goal survive 10
+//! pieces TLJ
+//! ..........
+//! ##.#######  # board rows, bottom-aligned, '#' filled and '.' empty
+//! ```
+
+use ::std::fs::File;
+use ::std::io::Read;
+use ::std::path::Path;
+
+use super::{State, Visible, Queue, Pos, MAIN_WIDTH, HEIGHT, VISIBLE_HEIGHT};
+use super::color;
+use super::event::Reason;
+use super::template::Kind;
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum Goal {
+    Lines(usize),
+    PerfectClear,
+    TspinDouble,
+    Survive(usize),
+}
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum Outcome {
+    Solved,
+    Failed,
+}
+
+#[derive(Clone, Debug)]
+pub struct Puzzle {
+    pub name: String,
+    pub goal: Goal,
+    pub pieces: Vec<Kind>,
+    /// Filled cells row by row, top to bottom; the last row sits at the bottom of the board.
+    pub board: Vec<Vec<bool>>,
+}
+
+/// Progress through a pack.
+pub struct Run {
+    pub pack: Vec<Puzzle>,
+    pub index: usize,
+    pieces: usize,
+    lines: usize,
+    /// How the last attempt went, left for the frontend to pick up.
+    pub outcome: Option<Outcome>,
+}
+
+impl Run {
+    pub fn new(pack: Vec<Puzzle>) -> Self {
+        Run {
+            pack: pack,
+            index: 0,
+            pieces: 0,
+            lines: 0,
+            outcome: None,
+        }
+    }
+
+    pub fn current(&self) -> Option<&Puzzle> {
+        self.pack.get(self.index)
+    }
+}
+
+pub fn load<P: AsRef<Path>>(path: P) -> Result<Vec<Puzzle>, String> {
+    let mut text = String::new();
+    File::open(path.as_ref())
+        .and_then(|mut file| file.read_to_string(&mut text))
+        .map_err(|err| format!("{}: {}", path.as_ref().display(), err))?;
+    parse(&text)
+}
+
+pub fn parse(text: &str) -> Result<Vec<Puzzle>, String> {
+    let mut pack: Vec<Puzzle> = Vec::new();
+    for (number, line) in text.lines().enumerate() {
+        let line = strip_comment(line).trim();
+        if line.is_empty() {
+            continue;
+        }
+        let error = |message: &str| format!("line {}: {}", number + 1, message);
+        let mut words = line.split_whitespace();
+        let keyword = words.next().unwrap();
+        if keyword == "puzzle" {
+            pack.push(Puzzle {
+                name: words.collect::<Vec<_>>().join(" "),
+                goal: Goal::Lines(1),
+                pieces: Vec::new(),
+                board: Vec::new(),
+            });
+            continue;
+        }
+        let puzzle = match pack.last_mut() {
+            Some(puzzle) => puzzle,
+            None => return Err(error("expected `puzzle` first")),
+        };
+        match keyword {
+            "goal" => {
+                let kind = words.next();
+                let count = words.next().and_then(|n| n.parse().ok());
+                puzzle.goal = match (kind, count) {
+                    (Some("lines"), Some(n)) => Goal::Lines(n),
+                    (Some("perfect_clear"), _) => Goal::PerfectClear,
+                    (Some("tspin_double"), _) => Goal::TspinDouble,
+                    (Some("survive"), Some(n)) => Goal::Survive(n),
+                    _ => return Err(error("unknown goal")),
+                };
+            },
+            "pieces" => {
+                for c in words.flat_map(|word| word.chars()) {
+                    match Kind::from_char(c) {
+                        Some(kind) => puzzle.pieces.push(kind),
+                        None => return Err(error("unknown piece")),
+                    }
+                }
+            },
+            row if row.chars().all(|c| c == '.' || c == '#') => {
+                if row.len() != MAIN_WIDTH {
+                    return Err(error(&format!("board rows must be {} wide", MAIN_WIDTH)));
+                }
+                puzzle.board.push(row.chars().map(|c| c == '#').collect());
+            },
+            _ => return Err(error("can't parse")),
+        }
+    }
+    for puzzle in pack.iter() {
+        if puzzle.pieces.is_empty() {
+            return Err(format!("puzzle `{}` has no pieces", puzzle.name));
+        }
+        if puzzle.board.len() > VISIBLE_HEIGHT {
+            return Err(format!("puzzle `{}` leaves no room to spawn", puzzle.name));
+        }
+    }
+    if pack.is_empty() {
+        return Err("no puzzles".to_string());
+    }
+    Ok(pack)
+}
+
+/// A `#` standing on its own starts a comment, one touching other cells is part of a board row.
+fn strip_comment(line: &str) -> &str {
+    let bytes = line.as_bytes();
+    let is_blank = |i: usize| bytes[i] == b' ' || bytes[i] == b'\t';
+    for i in 0..bytes.len() {
+        let is_alone = (i == 0 || is_blank(i - 1)) && (i + 1 == bytes.len() || is_blank(i + 1));
+        if bytes[i] == b'#' && is_alone {
+            return &line[..i];
+        }
+    }
+    line
+}
+
+impl State {
+    pub fn start_puzzles(&mut self, pack: Vec<Puzzle>) {
+        self.puzzles = Run::new(pack);
+        self.load_puzzle();
+    }
+
+    /// Sets the board and the queue up for the current puzzle, from scratch.
+    fn load_puzzle(&mut self) {
+        let puzzle = match self.puzzles.current() {
+            Some(puzzle) => puzzle.clone(),
+            None => return,
+        };
+        self.puzzles.pieces = 0;
+        self.puzzles.lines = 0;
+        self.clear_board();
+        let top = HEIGHT - puzzle.board.len();
+        for (y, row) in puzzle.board.iter().enumerate() {
+            for (x, &is_filled) in row.iter().enumerate() {
+                if is_filled {
+                    *self.main.tile_mut(Pos { x: x, y: top + y }) = color::GARBAGE;
+                }
+            }
+        }
+        self.queue = Queue::fixed(&puzzle.pieces, self.ruleset);
+        self.held = None;
+        self.redraw_hold();
+        if self.spawn_piece().is_ok() {
+            self.draw_piece(Visible::Yes);
+        }
+    }
+
+    /// Follows up a lock in puzzle mode: moves on when the goal is met, starts over
+    /// when it can't be met any more, otherwise brings in the next piece. Only running
+    /// out of puzzles ends the game.
+    pub fn advance_puzzle(&mut self, rows: usize, is_tspin: bool) {
+        let goal = match self.puzzles.current() {
+            Some(puzzle) => puzzle.goal,
+            None => return,
+        };
+        self.puzzles.pieces += 1;
+        self.puzzles.lines += rows;
+        let is_solved = match goal {
+            Goal::Lines(n) => self.puzzles.lines >= n,
+            Goal::PerfectClear => rows > 0 && self.is_board_empty(),
+            Goal::TspinDouble => is_tspin && rows == 2,
+            Goal::Survive(n) => self.puzzles.pieces >= n,
+        };
+        if !is_solved {
+            // out of pieces, or the next one has no room
+            match self.spawn_piece() {
+                Ok(()) => self.draw_piece(Visible::Yes),
+                Err(_) => self.fail_puzzle(),
+            }
+            return;
+        }
+        self.puzzles.outcome = Some(Outcome::Solved);
+        self.puzzles.index += 1;
+        if self.puzzles.current().is_some() {
+            self.load_puzzle();
+        }
+        else {
+            self.game_over(Reason::Finished);
+        }
+    }
+
+    /// Starts the current puzzle over.
+    pub fn fail_puzzle(&mut self) {
+        self.puzzles.outcome = Some(Outcome::Failed);
+        self.load_puzzle();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use config::Config;
+    use state::{State, Pos, Visible, MAIN_WIDTH};
+    use state::color::{self, Color};
+    use state::event::Event;
+    use state::mode::Mode;
+    use state::template::Kind;
+    use super::{parse, Outcome};
+
+    fn puzzle_state(pieces: &str) -> State {
+        let mut config = Config::default();
+        config.mode = Mode::Puzzle;
+        let mut state = State::new(&config);
+        let text = format!("puzzle Test\ngoal lines 1\npieces {}\n##.#######\n", pieces);
+        state.start_puzzles(parse(&text).unwrap());
+        state
+    }
+
+    #[test]
+    fn hold_swaps_in_the_next_puzzle_piece() {
+        let mut state = puzzle_state("TI");
+        state.hold_piece();
+        assert_eq!(state.held.map(|piece| piece.template.1), Some(Kind::T));
+        assert_eq!(state.piece.template.1, Kind::I);
+    }
+
+    #[test]
+    fn hold_is_refused_with_no_pieces_left() {
+        let mut state = puzzle_state("T");
+        state.hold_piece();
+        assert!(state.held.is_none());
+        assert_eq!(state.piece.template.1, Kind::T);
+        let cells = state.piece.try_into(&state.main).unwrap();
+        assert!(cells.iter().all(|&pos| *state.main.tile(pos) != Color::default()));
+        assert!(!state.is_gameover);
+    }
+
+    #[test]
+    fn blocked_spawn_fails_the_puzzle_not_the_game() {
+        let mut state = puzzle_state("TI");
+        state.draw_piece(Visible::No);
+        for y in 0..4 {
+            for x in 0..MAIN_WIDTH {
+                *state.main.tile_mut(Pos { x: x, y: y }) = color::GARBAGE;
+            }
+        }
+        state.take_events();
+        state.advance_puzzle(0, false);
+        assert!(!state.is_gameover);
+        assert_eq!(state.puzzles.outcome, Some(Outcome::Failed));
+        assert!(!state.take_events().iter().any(|event| match *event { Event::GameOver { .. } => true, _ => false }));
+        assert_eq!(state.puzzles.index, 0);
+        assert_eq!(state.piece.template.1, Kind::T);
+        assert_eq!(*state.main.tile(Pos { x: 0, y: 0 }), Color::default());
+    }
+}
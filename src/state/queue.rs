@@ -1,23 +1,60 @@
-use super::Generate;
-
 use ::std::collections::VecDeque;
 
-pub struct Queue<T> {
-    pub data: VecDeque<T>
+use super::Piece;
+use super::randomizer::Randomizer;
+use super::ruleset::Ruleset;
+use super::template::Kind;
+
+#[derive(Clone)]
+pub struct Queue {
+    pub data: VecDeque<Piece>,
+    /// Pieces kept ahead of the falling one, as many as the preview shows.
+    size: usize,
+    /// Random queues refill themselves, fixed ones have no randomizer and run dry.
+    randomizer: Option<Randomizer>,
+    ruleset: Ruleset,
 }
 
-impl<T: Generate> Queue<T> {
-    pub fn with_capacity(size: usize) -> Self {
-        let mut data = VecDeque::with_capacity(size);
-        for _ in 0..3 {
-            data.push_back(T::generate());
+impl Queue {
+    pub fn with_capacity(size: usize, ruleset: Ruleset) -> Self {
+        let mut queue = Queue {
+            data: VecDeque::with_capacity(size),
+            size: size,
+            randomizer: Some(ruleset.randomizer()),
+            ruleset: ruleset,
+        };
+        for _ in 0..size {
+            queue.refill();
+        }
+        queue
+    }
+
+    pub fn fixed(kinds: &[Kind], ruleset: Ruleset) -> Self {
+        Queue {
+            data: kinds.iter().map(|&kind| ruleset.piece(kind)).collect(),
+            size: kinds.len(),
+            randomizer: None,
+            ruleset: ruleset,
         }
-        Queue { data: data }
     }
 
-    pub fn next(&mut self) -> T {
-        let next = self.data.pop_front();
-        self.data.push_back(T::generate());
-        next.unwrap()
+    /// Whether there are no pieces left, which only happens to a fixed queue.
+    pub fn is_empty(&self) -> bool {
+        self.randomizer.is_none() && self.data.is_empty()
+    }
+
+    /// Takes the next piece, topping the queue up first unless a piece put in by hand
+    /// already makes it longer than its size.
+    pub fn next(&mut self) -> Option<Piece> {
+        if self.data.len() <= self.size {
+            self.refill();
+        }
+        self.data.pop_front()
+    }
+
+    fn refill(&mut self) {
+        if let Some(ref mut randomizer) = self.randomizer {
+            self.data.push_back(self.ruleset.piece(randomizer.next()));
+        }
     }
 }
@@ -0,0 +1,65 @@
+use rand;
+use rand::Rng;
+
+use super::template::Kind;
+
+static KINDS: [Kind; 7] = [Kind::I, Kind::T, Kind::O, Kind::J, Kind::L, Kind::S, Kind::Z];
+
+/// Deals the kinds of the pieces that fill the queue.
+#[derive(Clone, Debug)]
+pub enum Randomizer {
+    /// Every kind is as likely every time.
+    Uniform,
+    /// TGM style: rerolls up to `rolls` times while the kind is one of the last four dealt,
+    /// and never starts with S, Z or O.
+    History { recent: [Kind; 4], rolls: u32, is_first: bool },
+    /// NES style: rolls one of eight, and rolls again among the seven once if that
+    /// came up eighth or gave the same kind as last time.
+    Nes { last: Option<Kind> },
+}
+
+impl Randomizer {
+    pub fn history(recent: [Kind; 4], rolls: u32) -> Self {
+        Randomizer::History { recent: recent, rolls: rolls, is_first: true }
+    }
+
+    pub fn next(&mut self) -> Kind {
+        let mut rng = rand::thread_rng();
+        match *self {
+            Randomizer::Uniform => KINDS[rng.gen_range(0, KINDS.len())],
+            Randomizer::History { ref mut recent, rolls, ref mut is_first } => {
+                let mut kind = KINDS[rng.gen_range(0, KINDS.len())];
+                if *is_first {
+                    while kind == Kind::S || kind == Kind::Z || kind == Kind::O {
+                        kind = KINDS[rng.gen_range(0, KINDS.len())];
+                    }
+                    *is_first = false;
+                }
+                else {
+                    for _ in 1..rolls {
+                        if !recent.contains(&kind) {
+                            break;
+                        }
+                        kind = KINDS[rng.gen_range(0, KINDS.len())];
+                    }
+                }
+                for i in (1..recent.len()).rev() {
+                    recent[i] = recent[i - 1];
+                }
+                recent[0] = kind;
+                kind
+            },
+            Randomizer::Nes { ref mut last } => {
+                let roll = rng.gen_range(0, KINDS.len() + 1);
+                let kind = if roll == KINDS.len() || Some(KINDS[roll]) == *last {
+                    KINDS[rng.gen_range(0, KINDS.len())]
+                }
+                else {
+                    KINDS[roll]
+                };
+                *last = Some(kind);
+                kind
+            },
+        }
+    }
+}
@@ -0,0 +1,428 @@
+//! Rotation systems and everything that goes with them: spawn orientations, kicks,
+//! the randomizer, how pieces drop and lock, and their colours.
+
+use ::std::cmp;
+use ::std::str::FromStr;
+
+use super::{State, Piece};
+use super::color::{self, Color};
+use super::error::Error;
+use super::event::Event;
+use super::kick;
+use super::randomizer::Randomizer;
+use super::template::{Template, DeltaPos, Kind};
+use super::Generate;
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum Rules {
+    /// Super Rotation System, as in guideline games.
+    Srs,
+    /// Arika Rotation System of the first TGM.
+    Ars,
+    /// ARS with the I piece floor and wall kicks of TGM3 and a six roll randomizer.
+    ArsTi,
+    /// NES Tetris: no kicks, no hold, no hard drop and a delay before each piece comes in.
+    Nes,
+}
+
+impl FromStr for Rules {
+    type Err = ();
+
+    fn from_str(s: &str) -> Result<Self, ()> {
+        match s {
+            "srs" => Ok(Rules::Srs),
+            "ars" => Ok(Rules::Ars),
+            "ars_ti" => Ok(Rules::ArsTi),
+            "nes" => Ok(Rules::Nes),
+            _ => Err(()),
+        }
+    }
+}
+
+pub const RULES: [Rules; 4] = [Rules::Srs, Rules::Ars, Rules::ArsTi, Rules::Nes];
+
+impl Rules {
+    /// Name in the config, as `from_str` takes it.
+    pub fn name(&self) -> &'static str {
+        match *self {
+            Rules::Srs => "srs",
+            Rules::Ars => "ars",
+            Rules::ArsTi => "ars_ti",
+            Rules::Nes => "nes",
+        }
+    }
+}
+
+/// What the drop key does.
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum DropStyle {
+    /// Drops the piece and locks it.
+    Hard,
+    /// Drops the piece and keeps it in play at the bottom.
+    Sonic,
+    /// Nothing; pieces only come down by gravity and soft drop.
+    None,
+}
+
+#[derive(Clone, Copy, Debug)]
+pub struct Ruleset {
+    pub rules: Rules,
+    /// Ticks a piece rests on the stack before it locks, reset by each row it drops.
+    /// Without one, a piece locks as soon as gravity can't pull it further.
+    pub lock_delay: Option<u32>,
+    pub drop: DropStyle,
+    /// Soft drop against the stack locks the piece.
+    pub is_soft_drop_lock: bool,
+    pub has_hold: bool,
+    pub has_half_turn: bool,
+    /// Ticks a sideways key is held before it repeats, and ticks between repeats.
+    /// Without it the keyboard's own repeat moves the piece.
+    pub das: Option<(u32, u32)>,
+    /// Ticks full rows stay on the board before they collapse.
+    pub line_clear_delay: u32,
+    /// Whether turns and hold can be asked for before the next piece comes in.
+    pub has_initial_actions: bool,
+}
+
+const NES_DAS_DELAY: u32 = 16;
+const NES_DAS_REPEAT: u32 = 6;
+const NES_MAX_ENTRY_DELAY: u32 = 18;
+/// The shortest the NES clear animation runs, it waits on a frame counter for up to three more.
+const NES_LINE_CLEAR_DELAY: u32 = 17;
+const ARS_ENTRY_DELAY: u32 = 30;
+const ARS_LINE_CLEAR_DELAY: u32 = 41;
+
+/// Cells of the ARS orientations in a 3x3 box, 4x4 for the I, clockwise from spawn.
+/// The centre of the box's second row is the piece's position.
+static ARS_I: [[(isize, isize); 4]; 4] = [
+    [(0, 1), (1, 1), (2, 1), (3, 1)],
+    [(2, 0), (2, 1), (2, 2), (2, 3)],
+    [(0, 1), (1, 1), (2, 1), (3, 1)],
+    [(2, 0), (2, 1), (2, 2), (2, 3)],
+];
+static ARS_T: [[(isize, isize); 4]; 4] = [
+    [(0, 1), (1, 1), (2, 1), (1, 2)],
+    [(1, 0), (0, 1), (1, 1), (1, 2)],
+    [(1, 1), (0, 2), (1, 2), (2, 2)],
+    [(1, 0), (1, 1), (2, 1), (1, 2)],
+];
+static ARS_O: [[(isize, isize); 4]; 4] = [
+    [(1, 1), (2, 1), (1, 2), (2, 2)],
+    [(1, 1), (2, 1), (1, 2), (2, 2)],
+    [(1, 1), (2, 1), (1, 2), (2, 2)],
+    [(1, 1), (2, 1), (1, 2), (2, 2)],
+];
+static ARS_J: [[(isize, isize); 4]; 4] = [
+    [(0, 1), (1, 1), (2, 1), (2, 2)],
+    [(1, 0), (1, 1), (0, 2), (1, 2)],
+    [(0, 1), (0, 2), (1, 2), (2, 2)],
+    [(1, 0), (2, 0), (1, 1), (1, 2)],
+];
+static ARS_L: [[(isize, isize); 4]; 4] = [
+    [(0, 1), (1, 1), (2, 1), (0, 2)],
+    [(0, 0), (1, 0), (1, 1), (1, 2)],
+    [(2, 1), (0, 2), (1, 2), (2, 2)],
+    [(1, 0), (1, 1), (1, 2), (2, 2)],
+];
+static ARS_S: [[(isize, isize); 4]; 4] = [
+    [(1, 1), (2, 1), (0, 2), (1, 2)],
+    [(0, 0), (0, 1), (1, 1), (1, 2)],
+    [(1, 1), (2, 1), (0, 2), (1, 2)],
+    [(0, 0), (0, 1), (1, 1), (1, 2)],
+];
+static ARS_Z: [[(isize, isize); 4]; 4] = [
+    [(0, 1), (1, 1), (1, 2), (2, 2)],
+    [(2, 0), (1, 1), (2, 1), (1, 2)],
+    [(0, 1), (1, 1), (1, 2), (2, 2)],
+    [(2, 0), (1, 1), (2, 1), (1, 2)],
+];
+
+/// Cells of the NES orientations about the piece's position, clockwise from spawn.
+/// The I, S and Z flip between two of them and the O never turns.
+static NES_I: [[(isize, isize); 4]; 4] = [
+    [(-2, 0), (-1, 0), (0, 0), (1, 0)],
+    [(0, -2), (0, -1), (0, 0), (0, 1)],
+    [(-2, 0), (-1, 0), (0, 0), (1, 0)],
+    [(0, -2), (0, -1), (0, 0), (0, 1)],
+];
+static NES_T: [[(isize, isize); 4]; 4] = [
+    [(-1, 0), (0, 0), (1, 0), (0, 1)],
+    [(0, -1), (-1, 0), (0, 0), (0, 1)],
+    [(0, -1), (-1, 0), (0, 0), (1, 0)],
+    [(0, -1), (0, 0), (1, 0), (0, 1)],
+];
+static NES_O: [[(isize, isize); 4]; 4] = [
+    [(-1, 0), (0, 0), (-1, 1), (0, 1)],
+    [(-1, 0), (0, 0), (-1, 1), (0, 1)],
+    [(-1, 0), (0, 0), (-1, 1), (0, 1)],
+    [(-1, 0), (0, 0), (-1, 1), (0, 1)],
+];
+static NES_J: [[(isize, isize); 4]; 4] = [
+    [(-1, 0), (0, 0), (1, 0), (1, 1)],
+    [(0, -1), (0, 0), (-1, 1), (0, 1)],
+    [(-1, -1), (-1, 0), (0, 0), (1, 0)],
+    [(0, -1), (1, -1), (0, 0), (0, 1)],
+];
+static NES_L: [[(isize, isize); 4]; 4] = [
+    [(-1, 0), (0, 0), (1, 0), (-1, 1)],
+    [(-1, -1), (0, -1), (0, 0), (0, 1)],
+    [(1, -1), (-1, 0), (0, 0), (1, 0)],
+    [(0, -1), (0, 0), (0, 1), (1, 1)],
+];
+static NES_S: [[(isize, isize); 4]; 4] = [
+    [(0, 0), (1, 0), (-1, 1), (0, 1)],
+    [(0, -1), (0, 0), (1, 0), (1, 1)],
+    [(0, 0), (1, 0), (-1, 1), (0, 1)],
+    [(0, -1), (0, 0), (1, 0), (1, 1)],
+];
+static NES_Z: [[(isize, isize); 4]; 4] = [
+    [(-1, 0), (0, 0), (0, 1), (1, 1)],
+    [(1, -1), (0, 0), (1, 0), (0, 1)],
+    [(-1, 0), (0, 0), (0, 1), (1, 1)],
+    [(1, -1), (0, 0), (1, 0), (0, 1)],
+];
+
+impl Ruleset {
+    pub fn new(rules: Rules) -> Self {
+        match rules {
+            Rules::Srs => Ruleset {
+                rules: rules,
+                lock_delay: None,
+                drop: DropStyle::Hard,
+                is_soft_drop_lock: false,
+                has_hold: true,
+                has_half_turn: true,
+                das: None,
+                line_clear_delay: 0,
+                has_initial_actions: true,
+            },
+            Rules::Ars | Rules::ArsTi => Ruleset {
+                rules: rules,
+                lock_delay: Some(30),
+                drop: DropStyle::Sonic,
+                is_soft_drop_lock: true,
+                has_hold: true,
+                has_half_turn: true,
+                das: None,
+                line_clear_delay: ARS_LINE_CLEAR_DELAY,
+                has_initial_actions: true,
+            },
+            Rules::Nes => Ruleset {
+                rules: rules,
+                lock_delay: None,
+                drop: DropStyle::None,
+                is_soft_drop_lock: false,
+                has_hold: false,
+                has_half_turn: false,
+                das: Some((NES_DAS_DELAY, NES_DAS_REPEAT)),
+                line_clear_delay: NES_LINE_CLEAR_DELAY,
+                has_initial_actions: false,
+            },
+        }
+    }
+
+    fn is_ars(&self) -> bool {
+        self.rules == Rules::Ars || self.rules == Rules::ArsTi
+    }
+
+    /// The template of `kind` turned `orientation` quarter turns clockwise from spawn.
+    pub fn template(&self, kind: Kind, orientation: usize) -> Template {
+        let (cells, centre) = match (self.rules, kind) {
+            (Rules::Srs, _) => {
+                let mut template = Template::of(kind);
+                for _ in 0..orientation % 4 {
+                    template = template.rotate_left();
+                }
+                return template;
+            },
+            (Rules::Nes, Kind::I) => (&NES_I, 0),
+            (Rules::Nes, Kind::T) => (&NES_T, 0),
+            (Rules::Nes, Kind::O) => (&NES_O, 0),
+            (Rules::Nes, Kind::J) => (&NES_J, 0),
+            (Rules::Nes, Kind::L) => (&NES_L, 0),
+            (Rules::Nes, Kind::S) => (&NES_S, 0),
+            (Rules::Nes, Kind::Z) => (&NES_Z, 0),
+            (_, Kind::I) => (&ARS_I, 1),
+            (_, Kind::T) => (&ARS_T, 1),
+            (_, Kind::O) => (&ARS_O, 1),
+            (_, Kind::J) => (&ARS_J, 1),
+            (_, Kind::L) => (&ARS_L, 1),
+            (_, Kind::S) => (&ARS_S, 1),
+            (_, Kind::Z) => (&ARS_Z, 1),
+        };
+        let mut template = Template::of(kind);
+        for (delta, &(x, y)) in template.0.iter_mut().zip(cells[orientation % 4].iter()) {
+            *delta = DeltaPos { dx: x - centre, dy: y - centre };
+        }
+        template
+    }
+
+    /// Quarter turns clockwise from spawn that give `template`.
+    pub fn orientation(&self, template: &Template) -> usize {
+        (0..4)
+            .find(|&turns| {
+                let other = self.template(template.1, turns);
+                other.0.iter().zip(template.0.iter()).all(|(a, b)| a.dx == b.dx && a.dy == b.dy)
+            })
+            .unwrap_or(0)
+    }
+
+    pub fn color(&self, kind: Kind) -> Color {
+        match (self.rules, kind) {
+            (Rules::Srs, _) => Color::generate(),
+            (Rules::Nes, Kind::I) | (Rules::Nes, Kind::T) | (Rules::Nes, Kind::O) => color::WHITE,
+            (Rules::Nes, Kind::J) | (Rules::Nes, Kind::S) => color::BLUE,
+            (Rules::Nes, _) => color::RED,
+            (_, Kind::I) => color::RED,
+            (_, Kind::T) => color::CYAN,
+            (_, Kind::O) => color::YELLOW,
+            (_, Kind::J) => color::BLUE,
+            (_, Kind::L) => color::ORANGE,
+            (_, Kind::S) => color::MAGENTA,
+            (_, Kind::Z) => color::GREEN,
+        }
+    }
+
+    pub fn randomizer(&self) -> Randomizer {
+        match self.rules {
+            Rules::Srs => Randomizer::Uniform,
+            Rules::Ars => Randomizer::history([Kind::Z; 4], 4),
+            Rules::ArsTi => Randomizer::history([Kind::Z, Kind::S, Kind::S, Kind::Z], 6),
+            Rules::Nes => Randomizer::Nes { last: None },
+        }
+    }
+
+    /// Ticks between a lock and the next piece, longer the lower on the board it locked.
+    /// `height` counts rows up from the floor to the lowest cell of the locked piece.
+    pub fn entry_delay(&self, height: usize) -> u32 {
+        match self.rules {
+            Rules::Nes => cmp::min(NES_MAX_ENTRY_DELAY, 10 + (height as u32 + 2) / 4 * 2),
+            Rules::Ars | Rules::ArsTi => ARS_ENTRY_DELAY,
+            Rules::Srs => 0,
+        }
+    }
+
+    /// Points for clearing `rows` at once at `level`.
+    pub fn clear_score(&self, rows: usize, level: u32) -> u64 {
+        let base = match rows {
+            1 => 40,
+            2 => 100,
+            3 => 300,
+            4 => 1200,
+            _ => 0,
+        };
+        match self.rules {
+            Rules::Nes => base * (level as u64 + 1),
+            _ => base,
+        }
+    }
+
+    /// The NES level after `lines` starting from `start`: the first rise comes after ten lines
+    /// per level started on, capped between a hundred lines and fifty fewer than that,
+    /// then one every ten lines.
+    pub fn nes_level(start: u32, lines: u32) -> u32 {
+        let first = cmp::min(start * 10 + 10, cmp::max(100, (start * 10).saturating_sub(50)));
+        if lines < first {
+            start
+        }
+        else {
+            start + 1 + (lines - first) / 10
+        }
+    }
+
+    /// A new piece of `kind` at the spawn position.
+    pub fn piece(&self, kind: Kind) -> Piece {
+        let mut piece = Piece::new(self.template(kind, 0));
+        piece.color = self.color(kind);
+        piece
+    }
+}
+
+impl State {
+    /// Turns the piece a quarter counterclockwise.
+    pub fn rotate_piece(&mut self) -> Result<(), Error> {
+        self.turn_piece(3)
+    }
+
+    /// Turns the piece around.
+    pub fn rotate_piece_half(&mut self) -> Result<(), Error> {
+        self.turn_piece(2)
+    }
+
+    /// Turns the piece `turns` quarters clockwise, trying the ruleset's kicks in turn.
+    pub fn turn_piece(&mut self, turns: usize) -> Result<(), Error> {
+        let kind = self.piece.template.1;
+        let from = self.ruleset.orientation(&self.piece.template);
+        let to = (from + turns) % 4;
+        let mut rotated = self.piece;
+        rotated.template = self.ruleset.template(kind, to);
+        let mut error = None;
+        for (index, kick) in self.kicks(kind, from, to).into_iter().enumerate() {
+            let mut kicked = rotated;
+            kicked.pos.x = (rotated.pos.x as isize + kick.dx) as usize;
+            kicked.pos.y = (rotated.pos.y as isize + kick.dy) as usize;
+            match self.fit(&kicked) {
+                Ok(()) => {
+                    self.piece = kicked;
+                    self.is_last_move_rotation = true;
+                    self.emit(Event::Rotated { kick: index });
+                    return Ok(());
+                },
+                Err(err) => {
+                    let is_centre_blocked = index == 0 && self.is_centre_blocked(kind, &err);
+                    error = error.or(Some(err));
+                    if is_centre_blocked {
+                        break;
+                    }
+                },
+            }
+        }
+        Err(error.unwrap_or(Error::OutOfBounds))
+    }
+
+    fn kicks(&self, kind: Kind, from: usize, to: usize) -> Vec<DeltaPos> {
+        let in_place = DeltaPos { dx: 0, dy: 0 };
+        match self.ruleset.rules {
+            Rules::Srs if (from + 2) % 4 == to => {
+                // the first SRS offsets turn the I about its true centre before kicking
+                let (a, b) = (kick::srs_offset(kind, from, 0), kick::srs_offset(kind, to, 0));
+                self.config.half_turn_kicks.offsets(from).iter()
+                    .map(|kick| DeltaPos { dx: kick.dx + a.dx - b.dx, dy: kick.dy + a.dy - b.dy })
+                    .collect()
+            },
+            Rules::Srs => kick::srs_quarter_turn(kind, from, to),
+            Rules::Nes => vec![in_place],
+            Rules::Ars | Rules::ArsTi => match kind {
+                Kind::O => vec![in_place],
+                Kind::I if self.ruleset.rules == Rules::Ars => vec![in_place],
+                Kind::I if to % 2 == 1 && self.is_grounded() => vec![
+                    in_place,
+                    DeltaPos { dx: 0, dy: -1 },
+                    DeltaPos { dx: 0, dy: -2 },
+                ],
+                Kind::I if to % 2 == 1 => vec![in_place],
+                Kind::I => vec![
+                    in_place,
+                    DeltaPos { dx: 1, dy: 0 },
+                    DeltaPos { dx: -1, dy: 0 },
+                    DeltaPos { dx: 2, dy: 0 },
+                ],
+                _ => vec![in_place, DeltaPos { dx: 1, dy: 0 }, DeltaPos { dx: -1, dy: 0 }],
+            },
+        }
+    }
+
+    /// The ARS centre column rule: a J, L or T doesn't kick when the first cell in its way,
+    /// reading row by row, is in the middle column.
+    fn is_centre_blocked(&self, kind: Kind, error: &Error) -> bool {
+        if !self.ruleset.is_ars() || (kind != Kind::J && kind != Kind::L && kind != Kind::T) {
+            return false;
+        }
+        match *error {
+            Error::Collision { ref cells } => cells.iter()
+                .min_by_key(|pos| (pos.y, pos.x))
+                .map_or(false, |pos| pos.x == self.piece.pos.x),
+            _ => false,
+        }
+    }
+}
@@ -0,0 +1,31 @@
+#[derive(Clone, Copy, Default, Debug)]
+pub struct Stats {
+    pub pieces: usize,
+    pub keys: usize,
+    pub lines: usize,
+    pub singles: usize,
+    pub doubles: usize,
+    pub triples: usize,
+    pub tetrises: usize,
+    pub tspins: usize,
+    pub garbage: usize,
+    pub perfect_clears: usize,
+    /// Times the stack came down to a shape a perfect clear could still be built from.
+    pub pc_chances: usize,
+}
+
+impl Stats {
+    pub fn count_clear(&mut self, rows: usize, is_tspin: bool) {
+        self.lines += rows;
+        match rows {
+            1 => self.singles += 1,
+            2 => self.doubles += 1,
+            3 => self.triples += 1,
+            4 => self.tetrises += 1,
+            _ => (),
+        }
+        if is_tspin && rows > 0 {
+            self.tspins += 1;
+        }
+    }
+}
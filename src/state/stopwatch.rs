@@ -0,0 +1,53 @@
+use ::std::time::Duration;
+
+use super::timer::{tick, TICKS_PER_SECOND};
+
+/// Game clock counted in simulation ticks, so it doesn't drift with frame rate.
+pub struct Stopwatch {
+    ticks: u32,
+    is_started: bool,
+    is_stopped: bool,
+}
+
+impl Stopwatch {
+    pub fn new() -> Self {
+        Stopwatch {
+            ticks: 0,
+            is_started: false,
+            is_stopped: false,
+        }
+    }
+
+    pub fn start(&mut self) {
+        self.is_started = true;
+    }
+
+    pub fn stop(&mut self) {
+        self.is_stopped = true;
+    }
+
+    pub fn tick(&mut self) {
+        if self.is_started && !self.is_stopped {
+            self.ticks += 1;
+        }
+    }
+
+    pub fn is_started(&self) -> bool {
+        self.is_started
+    }
+
+    pub fn elapsed(&self) -> Duration {
+        let secs = self.ticks / TICKS_PER_SECOND;
+        Duration::from_secs(secs as u64) + tick() * (self.ticks % TICKS_PER_SECOND)
+    }
+}
+
+pub fn seconds(duration: Duration) -> f64 {
+    duration.as_secs() as f64 + duration.subsec_nanos() as f64 / 1e9
+}
+
+/// Formats as `m:ss.mmm`.
+pub fn format(duration: Duration) -> String {
+    let millis = duration.subsec_nanos() / 1_000_000;
+    format!("{}:{:02}.{:03}", duration.as_secs() / 60, duration.as_secs() % 60, millis)
+}
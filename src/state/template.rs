@@ -1,11 +1,24 @@
-use super::Generate;
-
 #[derive(Clone, Copy, PartialEq, Debug)]
 pub enum Kind {
     I, T, O, J, L, S, Z,
 }
 
-#[derive(Copy, Clone, Debug)]
+impl Kind {
+    pub fn from_char(c: char) -> Option<Kind> {
+        match c.to_ascii_uppercase() {
+            'I' => Some(Kind::I),
+            'T' => Some(Kind::T),
+            'O' => Some(Kind::O),
+            'J' => Some(Kind::J),
+            'L' => Some(Kind::L),
+            'S' => Some(Kind::S),
+            'Z' => Some(Kind::Z),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Copy, Clone, PartialEq, Debug)]
 pub struct DeltaPos {
     pub dx: isize,
     pub dy: isize,
@@ -14,19 +27,20 @@ pub struct DeltaPos {
 #[derive(Clone, Copy, Debug)]
 pub struct Template(pub [DeltaPos; 4], pub Kind);
 
-impl Generate for Template {
-    fn generate() -> Self {
-        use rand;
-        use rand::Rng;
-
-        let mut rng = rand::thread_rng();
-        static TEMPLATES: [Template; 7] = [I, T, O, J, L, S, Z];
-        TEMPLATES[rng.gen_range(0, 7)]
+impl Template {
+    /// The template in its spawn orientation.
+    pub fn of(kind: Kind) -> Self {
+        match kind {
+            Kind::I => I,
+            Kind::T => T,
+            Kind::O => O,
+            Kind::J => J,
+            Kind::L => L,
+            Kind::S => S,
+            Kind::Z => Z,
+        }
     }
-}
 
-impl Template {
-    #[allow(dead_code)]
     pub fn rotate_left(&self) -> Self {
         let mut piece = self.clone();
         if self.1 == Kind::O {
@@ -40,17 +54,17 @@ impl Template {
         piece
     }
 
-    pub fn rotate_right(&self) -> Self {
-        let mut piece = self.clone();
-        if self.1 == Kind::O {
-            return piece;
-        }
-
-        for (output, input) in piece.0.iter_mut().zip(self.0.iter()) {
-            output.dx =  input.dy;
-            output.dy = -input.dx;
+    /// Top-left and bottom-right corners of the template's bounding box.
+    pub fn bounds(&self) -> (DeltaPos, DeltaPos) {
+        let mut min = self.0[0];
+        let mut max = self.0[0];
+        for delta in self.0.iter() {
+            min.dx = min.dx.min(delta.dx);
+            min.dy = min.dy.min(delta.dy);
+            max.dx = max.dx.max(delta.dx);
+            max.dy = max.dy.max(delta.dy);
         }
-        piece
+        (min, max)
     }
 }
 
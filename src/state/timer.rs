@@ -1,25 +1,47 @@
-use ::std::time::{Duration, Instant};
+use ::std::cmp;
+use ::std::time::Duration;
+
+pub const TICKS_PER_SECOND: u32 = 60;
+const MAX_GRAVITY_LEVEL: u32 = 20;
+/// Frames per row on NES levels 0 to 9; faster levels share the counts further down.
+const NES_FRAMES: [u32; 10] = [48, 43, 38, 33, 28, 23, 18, 13, 8, 6];
+
+/// Length of one simulation step.
+pub fn tick() -> Duration {
+    Duration::new(0, 1_000_000_000 / TICKS_PER_SECOND)
+}
 
 pub struct Timer {
-    accumulator: Instant,
+    accumulator: Duration,
     threshold: Duration,
 }
 
 impl Timer {
     pub fn new() -> Self {
         Timer {
-            accumulator: Instant::now(),
+            accumulator: Duration::from_secs(0),
             threshold: Duration::from_millis(350),
         }
     }
 
-    pub fn is_up(&mut self) -> bool {
-        if self.accumulator.elapsed() > self.threshold {
-            self.accumulator = Instant::now();
-            true
-        } else {
-            false
+    /// Advances the timer by one tick, meant to be called once per simulation step.
+    /// Returns how many rows the piece should fall, which exceeds one at high speeds.
+    pub fn drops(&mut self) -> u32 {
+        self.accumulator += tick();
+        let mut drops = 0;
+        while self.accumulator >= self.threshold {
+            self.accumulator -= self.threshold;
+            drops += 1;
         }
+        drops
+    }
+
+    pub fn threshold(&self) -> Duration {
+        self.threshold
+    }
+
+    pub fn set_threshold(&mut self, threshold: Duration) {
+        self.threshold = threshold;
     }
 
     pub fn lower_threshold(&mut self) {
@@ -27,4 +49,24 @@ impl Timer {
             self.threshold -= Duration::from_millis(5);
         }
     }
-}
\ No newline at end of file
+}
+
+/// Time per row at `level` from the guideline curve, which flattens out at level 20.
+pub fn level_gravity(level: u32) -> Duration {
+    let level = cmp::min(cmp::max(level, 1), MAX_GRAVITY_LEVEL) as f64;
+    let secs = (0.8 - (level - 1.0) * 0.007).powf(level - 1.0);
+    Duration::new(0, (secs * 1e9) as u32)
+}
+
+/// Time per row at NES `level`, a frame a row from the level 29 killscreen on.
+pub fn nes_gravity(level: u32) -> Duration {
+    let frames = match level {
+        level if level < 10 => NES_FRAMES[level as usize],
+        level if level < 13 => 5,
+        level if level < 16 => 4,
+        level if level < 19 => 3,
+        level if level < 29 => 2,
+        _ => 1,
+    };
+    tick() * frames
+}